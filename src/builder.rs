@@ -0,0 +1,222 @@
+//! Setting up a bisection without going through command line arguments, for
+//! users of the library.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::bail;
+use clap::Parser;
+
+use crate::{validate_jobs, validate_trials, Bound, Config, Opts, RegressOn, Reporter};
+
+/// Sets up a [`Config`] from options given one at a time. The options which
+/// are not set keep the defaults of `cargo bisect-rustc`.
+///
+/// ```no_run
+/// use cargo_bisect_rustc::{Bound, Config, Reporter};
+///
+/// let cfg = Config::builder()
+///     .start("2022-08-01".parse::<Bound>()?)
+///     .end("2022-08-10".parse::<Bound>()?)
+///     .script("./test.sh")
+///     .build(Reporter::stderr())?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ConfigBuilder {
+    args: Opts,
+}
+
+impl Config {
+    /// Starts setting up a bisection with the defaults of `cargo bisect-rustc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current directory, which is the default test directory,
+    /// no longer exists.
+    #[must_use]
+    pub fn builder() -> ConfigBuilder {
+        // The defaults are declared once, along with the command line options.
+        let args = Opts::try_parse_from(["cargo-bisect-rustc"])
+            .expect("the defaults of the command line options are valid");
+        ConfigBuilder { args }
+    }
+}
+
+impl ConfigBuilder {
+    /// The start of the range, without the regression, like `--start`.
+    #[must_use]
+    pub fn start(mut self, bound: Bound) -> Self {
+        self.args.start = Some(bound);
+        self
+    }
+
+    /// The end of the range, with the regression, like `--end`.
+    #[must_use]
+    pub fn end(mut self, bound: Bound) -> Self {
+        self.args.end = Some(bound);
+        self
+    }
+
+    /// Bisects CI artifacts rather than nightlies, like `--by-commit`.
+    #[must_use]
+    pub fn by_commit(mut self, by_commit: bool) -> Self {
+        self.args.by_commit = by_commit;
+        self
+    }
+
+    /// Bisects stable releases first, like `--by-release`.
+    #[must_use]
+    pub fn by_release(mut self, by_release: bool) -> Self {
+        self.args.by_release = by_release;
+        self
+    }
+
+    /// The host triple of the toolchains, like `--host`.
+    #[must_use]
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.args.host = host.into();
+        self
+    }
+
+    /// The target to build for, like `--target`.
+    #[must_use]
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.args.target = Some(target.into());
+        self
+    }
+
+    /// Downloads the alt builds of CI artifacts, like `--alt`.
+    #[must_use]
+    pub fn alt(mut self, alt: bool) -> Self {
+        self.args.alt = alt;
+        self
+    }
+
+    /// Additional components to install, like `--component`.
+    #[must_use]
+    pub fn components(mut self, components: impl IntoIterator<Item = String>) -> Self {
+        self.args.components = components.into_iter().collect();
+        self
+    }
+
+    /// Keeps the toolchains once they were tested, like `--preserve`.
+    #[must_use]
+    pub fn preserve(mut self, preserve: bool) -> Self {
+        self.args.preserve = preserve;
+        self
+    }
+
+    /// The directory to run the test in, like `--test-dir`.
+    #[must_use]
+    pub fn test_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.args.test_dir = dir.into();
+        self
+    }
+
+    /// Runs `script` as the test instead of cargo, like `--script`.
+    #[must_use]
+    pub fn script(mut self, script: impl Into<PathBuf>) -> Self {
+        self.args.script = Some(script.into());
+        self
+    }
+
+    /// The arguments to pass to cargo or the script, like those after `--`.
+    #[must_use]
+    pub fn command_args(mut self, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.args.command_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// What counts as the regression, like `--regress`. See also
+    /// [`Config::with_predicate`].
+    #[must_use]
+    pub fn regress(mut self, regress: RegressOn) -> Self {
+        self.args.regress = regress;
+        self
+    }
+
+    /// Kills the test after `timeout`, rounded up to whole seconds, like
+    /// `--timeout`.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        self.args.timeout = Some(usize::try_from(secs).unwrap_or(usize::MAX));
+        self
+    }
+
+    /// Installs and tests up to `jobs` toolchains in parallel, like `--jobs`.
+    #[must_use]
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.args.jobs = jobs;
+        self
+    }
+
+    /// Runs the test this many times on each toolchain, like `--trials`.
+    #[must_use]
+    pub fn trials(mut self, trials: u32) -> Self {
+        self.args.trials = trials;
+        self
+    }
+
+    /// Sets up the bisection, reporting its progress to `reporter`.
+    ///
+    /// Unlike with [`Config::from_command_line`], no session journal is
+    /// recorded, since it could not be resumed with `--resume`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the options are invalid, and otherwise if the bounds of the
+    /// range cannot be resolved or the toolchains cannot be stored.
+    pub fn build(self, reporter: Reporter) -> anyhow::Result<Config> {
+        let args = self.args;
+        if !args.test_dir.is_dir() {
+            bail!("{} is not an existing directory", args.test_dir.display());
+        }
+        if let Some(ref script) = args.script {
+            if !script.is_file() {
+                bail!("{} is not an existing file", script.display());
+            }
+        }
+        validate_jobs(&args.jobs.to_string())?;
+        validate_trials(&args.trials.to_string())?;
+        Config::from_opts(args, Vec::new(), None, reporter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_options() {
+        let build = |builder: ConfigBuilder| {
+            builder
+                .build(Reporter::new(|_| {}))
+                .err()
+                .expect("invalid options are rejected")
+        };
+
+        let error = build(Config::builder().test_dir("/nonexistent/test-dir"));
+        assert!(
+            error.to_string().contains("not an existing directory"),
+            "{error}"
+        );
+        let error = build(Config::builder().script("/nonexistent/test.sh"));
+        assert!(
+            error.to_string().contains("not an existing file"),
+            "{error}"
+        );
+        let error = build(Config::builder().jobs(0));
+        assert!(error.to_string().contains("at least 1"), "{error}");
+        let error = build(Config::builder().trials(0));
+        assert!(error.to_string().contains("at least 1"), "{error}");
+    }
+
+    #[test]
+    fn rounds_timeout_up() {
+        let builder = Config::builder().timeout(Duration::from_millis(1500));
+        assert_eq!(builder.args.timeout, Some(2));
+    }
+}
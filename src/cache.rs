@@ -0,0 +1,246 @@
+//! Cache of downloaded toolchain tarballs, shared between runs so that
+//! bisecting overlapping ranges does not download the same artifacts again.
+//!
+//! Tarballs are stored under the sha256 of their contents, once they were
+//! downloaded completely and verified against their published checksum, so
+//! that the same tarball from different mirrors is only stored once. An index
+//! maps the URL each tarball was downloaded from to its checksum, so that a
+//! tarball can be used without contacting the server even when its checksum
+//! is not known up front. A tarball is checked against its checksum before it
+//! is used. The modification time of a tarball is bumped whenever it is used,
+//! and the least recently used tarballs are evicted once the cache grows over
+//! its size limit.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::Reporter;
+
+/// Subdirectory with the index from URLs to checksums.
+const INDEX_DIR: &str = "urls";
+
+#[derive(Clone, Debug)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl Cache {
+    /// Opens the cache in `dir`, which may hold up to `max_size` bytes.
+    pub(crate) fn new(dir: PathBuf, max_size: u64) -> io::Result<Cache> {
+        fs::create_dir_all(dir.join(INDEX_DIR))?;
+        Ok(Cache { dir, max_size })
+    }
+
+    fn path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256)
+    }
+
+    fn index_path(&self, url: &str) -> PathBuf {
+        let hash = Sha256::digest(url.as_bytes());
+        self.dir.join(INDEX_DIR).join(format!("{hash:x}"))
+    }
+
+    /// Opens the tarball with the checksum `sha256`, or if it is not known,
+    /// the one downloaded from `url`, if it is in the cache and intact. A
+    /// corrupt tarball is removed.
+    pub(crate) fn get(&self, url: &str, sha256: Option<&str>) -> Option<File> {
+        let sha256 = match sha256 {
+            Some(sha256) => sha256.to_string(),
+            None => fs::read_to_string(self.index_path(url)).ok()?,
+        };
+        // Checksums come from servers and the index, and must not name
+        // anything outside of the cache.
+        if sha256.is_empty() || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let path = self.path(&sha256);
+        let mut file = File::open(&path).ok()?;
+        match hash(&mut file) {
+            Ok(actual) if actual == sha256 => {}
+            _ => {
+                debug!(
+                    "removing corrupt {} from the download cache",
+                    path.display()
+                );
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+        }
+        debug!("using cached <{}> from {}", url, path.display());
+        // Mark the tarball as recently used; failing to do so only affects
+        // the eviction order.
+        let _ = filetime::set_file_mtime(&path, FileTime::now());
+        Some(file)
+    }
+
+    /// Stores the tarball of `size` bytes downloaded from `url` by reading all
+    /// of `tarball`, then returns the cached file.
+    ///
+    /// The tarball only becomes visible to other runs once it was written
+    /// completely. Failing to evict old tarballs afterwards is only reported
+    /// as a warning.
+    pub(crate) fn insert<R: Read>(
+        &self,
+        url: &str,
+        size: u64,
+        mut tarball: R,
+        reporter: &Reporter,
+    ) -> io::Result<File> {
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        let written = io::copy(&mut tarball, &mut tmp)?;
+        if written != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("expected {size} bytes from {url}, got {written}"),
+            ));
+        }
+        tmp.rewind()?;
+        let sha256 = hash(tmp.as_file_mut())?;
+        let path = self.path(&sha256);
+        tmp.persist(&path).map_err(|e| e.error)?;
+
+        let mut index = tempfile::NamedTempFile::new_in(self.dir.join(INDEX_DIR))?;
+        index.write_all(sha256.as_bytes())?;
+        index.persist(self.index_path(url)).map_err(|e| e.error)?;
+
+        if let Err(e) = self.evict(&path) {
+            warning!(
+                reporter,
+                "failed to evict old downloads from {}: {e}",
+                self.dir.display()
+            );
+        }
+        File::open(path)
+    }
+
+    /// Removes the least recently used tarballs, other than `keep`, until the
+    /// cache fits in its size limit.
+    fn evict(&self, keep: &Path) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            // Skip tarballs which are still being downloaded.
+            if !metadata.is_file() || entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            entries.push((
+                FileTime::from_last_modification_time(&metadata),
+                metadata.len(),
+                entry.path(),
+            ));
+        }
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            debug!("evicting {} from the download cache", path.display());
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+        // Entries of the index whose tarball was evicted are useless.
+        for entry in fs::read_dir(self.dir.join(INDEX_DIR))? {
+            let path = entry?.path();
+            if let Ok(sha256) = fs::read_to_string(&path) {
+                if !self.path(&sha256).is_file() {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hashes the contents of `file`, then rewinds it.
+fn hash(file: &mut File) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    file.rewind()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(contents: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(contents))
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf(), 10).unwrap();
+        let reporter = Reporter::new(|_| {});
+        let (a, b, c) = (
+            "https://a/x.tar.xz",
+            "https://b/x.tar.xz",
+            "https://c/x.tar.xz",
+        );
+
+        cache.insert(a, 4, &b"aaaa"[..], &reporter).unwrap();
+        cache.insert(b, 4, &b"bbbb"[..], &reporter).unwrap();
+        let (path_a, path_b) = (cache.path(&sha256(b"aaaa")), cache.path(&sha256(b"bbbb")));
+        filetime::set_file_mtime(path_a, FileTime::from_unix_time(0, 0)).unwrap();
+        filetime::set_file_mtime(path_b, FileTime::from_unix_time(1, 0)).unwrap();
+        // Using `a` makes `b` the least recently used tarball.
+        assert!(cache.get(a, None).is_some());
+        assert!(cache.get(c, None).is_none());
+
+        cache.insert(c, 4, &b"cccc"[..], &reporter).unwrap();
+        assert!(cache.get(a, None).is_some());
+        assert!(cache.get(b, None).is_none());
+        assert!(cache.get(c, None).is_some());
+        assert!(!cache.index_path(b).exists());
+    }
+
+    #[test]
+    fn shares_tarballs_between_mirrors() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf(), 10).unwrap();
+        let reporter = Reporter::new(|_| {});
+        let sha256 = sha256(b"aaaa");
+
+        cache
+            .insert("https://a/x.tar.xz", 4, &b"aaaa"[..], &reporter)
+            .unwrap();
+        assert!(cache.get("https://mirror/x.tar.xz", None).is_none());
+        assert!(cache
+            .get("https://mirror/x.tar.xz", Some(&sha256))
+            .is_some());
+        cache
+            .insert("https://mirror/x.tar.xz", 4, &b"aaaa"[..], &reporter)
+            .unwrap();
+        // Both URLs map to the same file.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        assert!(cache.get("../x", Some("../x")).is_none());
+    }
+
+    #[test]
+    fn rejects_corrupt_tarballs() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf(), 10).unwrap();
+        let reporter = Reporter::new(|_| {});
+        let url = "https://a/x.tar.xz";
+        assert!(cache.insert(url, 4, &b"aa"[..], &reporter).is_err());
+        assert!(cache.get(url, None).is_none());
+        // Only the index directory is left.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        cache.insert(url, 4, &b"aaaa"[..], &reporter).unwrap();
+        fs::write(cache.path(&sha256(b"aaaa")), b"abcd").unwrap();
+        assert!(cache.get(url, None).is_none());
+        assert!(!cache.path(&sha256(b"aaaa")).exists());
+    }
+}
@@ -0,0 +1,434 @@
+//! Running test commands, with an optional timeout, and measuring their peak
+//! memory usage for `--regress=peak-memory`.
+//!
+//! On Unix, a test command with a timeout runs in its own process group, so
+//! that the timeout kills the processes it spawned (such as the rustc
+//! processes spawned by cargo) along with it. Such a group is no longer in the
+//! foreground of the terminal, so it is killed when the bisector is
+//! interrupted. Without a timeout, the command stays in the group of the
+//! bisector and gets Ctrl-C and terminal input as usual.
+
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::progress::{Progress, Reporter};
+
+/// How long to keep reading the output of a command once it has exited, in
+/// case a process it left behind still holds its pipes.
+const PIPE_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// A test command which ran to completion, or until it was killed.
+pub(crate) struct Finished {
+    pub(crate) output: Output,
+    /// Peak resident set size in bytes, where supported.
+    ///
+    /// The peak covers the largest of the processes in the tree of the
+    /// command which were waited for, so it includes the rustc processes
+    /// spawned by cargo.
+    pub(crate) peak_rss: Option<u64>,
+    /// Whether the command was killed for exceeding its timeout.
+    pub(crate) timed_out: bool,
+}
+
+/// Runs `cmd` like [`Command::output`], killing it along with the processes it
+/// spawned if it runs for longer than `timeout`. Its output is also handed to
+/// `reporter` as it is read, if given.
+///
+/// Processes left behind by the command do not keep this waiting: with a
+/// timeout they are killed along with the rest of the group once the command
+/// exits, and in any case their output is only read for a short while after
+/// that.
+pub(crate) fn run(
+    cmd: &mut Command,
+    timeout: Option<Duration>,
+    reporter: Option<&Reporter>,
+) -> io::Result<Finished> {
+    #[cfg(unix)]
+    if timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    let mut child = cmd.spawn()?;
+    let stdout = Pipe::read(child.stdout.take(), reporter.cloned());
+    let stderr = Pipe::read(child.stderr.take(), reporter.cloned());
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let (status, peak_rss) = match timeout {
+        Some(timeout) => wait_with_timeout(&mut child, timeout, &timed_out)?,
+        None => wait(&mut child)?,
+    };
+
+    let deadline = Instant::now() + PIPE_GRACE_PERIOD;
+    Ok(Finished {
+        output: Output {
+            status,
+            stdout: stdout.finish(deadline),
+            stderr: stderr.finish(deadline),
+        },
+        peak_rss,
+        timed_out: timed_out.load(Ordering::SeqCst),
+    })
+}
+
+/// A pipe of the child, drained on a separate thread.
+struct Pipe {
+    buf: Arc<Mutex<Vec<u8>>>,
+    /// Disconnected once the thread reached the end of the pipe.
+    eof: mpsc::Receiver<()>,
+}
+
+impl Pipe {
+    fn read<R: Read + Send + 'static>(pipe: Option<R>, reporter: Option<Reporter>) -> Pipe {
+        // Both pipes are drained concurrently so that the child does not
+        // block on a full pipe while we wait for it.
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let (eof, reached) = mpsc::channel();
+        {
+            let buf = Arc::clone(&buf);
+            thread::spawn(move || {
+                let _eof = eof;
+                if let Some(mut pipe) = pipe {
+                    let mut chunk = [0; 8192];
+                    loop {
+                        match pipe.read(&mut chunk) {
+                            Ok(0) => break,
+                            Ok(n) => {
+                                if let Some(reporter) = &reporter {
+                                    reporter.report(&Progress::TestOutput(&chunk[..n]));
+                                }
+                                buf.lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .extend_from_slice(&chunk[..n]);
+                            }
+                            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                            Err(_) => break,
+                        }
+                    }
+                }
+            });
+        }
+        Pipe { buf, eof: reached }
+    }
+
+    /// Returns what was read until the end of the pipe, or until `deadline`
+    /// if something else still holds it open. The thread is left to finish
+    /// on its own in that case.
+    fn finish(self, deadline: Instant) -> Vec<u8> {
+        let _ = self
+            .eof
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        let mut buf = self.buf.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut *buf)
+    }
+}
+
+/// Waits for `child` while a watchdog thread kills its process group once
+/// `timeout` has passed. The rest of the group is killed as well once `child`
+/// exits.
+#[cfg(unix)]
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+    timed_out: &Arc<AtomicBool>,
+) -> io::Result<(ExitStatus, Option<u64>)> {
+    let pgid = libc::pid_t::try_from(child.id()).expect("pid fits in pid_t");
+    let _group = interrupt::Registered::new(pgid);
+    let (done, cancelled) = mpsc::channel::<()>();
+    let watchdog = {
+        let timed_out = Arc::clone(timed_out);
+        thread::spawn(move || {
+            if cancelled.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                timed_out.store(true, Ordering::SeqCst);
+                // SAFETY: kill has no memory safety requirements. The group
+                // is still around, since its leader is only reaped below.
+                unsafe {
+                    libc::kill(-pgid, libc::SIGKILL);
+                }
+            }
+        })
+    };
+    let exited = wait_for_exit(pgid);
+    drop(done);
+    let _ = watchdog.join();
+    // SAFETY: as above, the leader has exited but was not reaped yet.
+    unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+    }
+    exited?;
+    wait(child)
+}
+
+/// Waits until the process `pid` has exited, without reaping it.
+#[cfg(unix)]
+fn wait_for_exit(pid: libc::pid_t) -> io::Result<()> {
+    let id = libc::id_t::try_from(pid).expect("pid is positive");
+    loop {
+        // SAFETY: `info` is plain old data, for which all zeroes is valid.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `info` is valid for writes, and `pid` is a child of ours
+        // which has not been waited for yet.
+        let waited = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                id,
+                std::ptr::addr_of_mut!(info),
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if waited == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Kills the process groups of the running test commands when the bisector
+/// is interrupted or terminated, since they do not get the signals sent to
+/// the foreground group of the terminal.
+#[cfg(unix)]
+mod interrupt {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Once;
+
+    /// How many groups can be registered at once, which is more than the
+    /// number of tests run in parallel.
+    const SLOTS: usize = 64;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FREE: AtomicI32 = AtomicI32::new(0);
+
+    /// The registered process groups, where 0 marks a free slot. Atomics are
+    /// used since the signal handler cannot take locks.
+    static GROUPS: [AtomicI32; SLOTS] = [FREE; SLOTS];
+
+    static INSTALL: Once = Once::new();
+
+    /// Registers a process group until dropped.
+    pub(super) struct Registered(Option<&'static AtomicI32>);
+
+    impl Registered {
+        pub(super) fn new(pgid: libc::pid_t) -> Registered {
+            INSTALL.call_once(install);
+            let slot = GROUPS.iter().find(|slot| {
+                slot.compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            });
+            Registered(slot)
+        }
+    }
+
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            if let Some(slot) = self.0 {
+                slot.store(0, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn install() {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only calls async-signal-safe functions.
+            unsafe {
+                // Leave signals which were ignored when we started ignored,
+                // as under nohup.
+                if libc::signal(signal, handler) == libc::SIG_IGN {
+                    libc::signal(signal, libc::SIG_IGN);
+                }
+            }
+        }
+    }
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        for slot in &GROUPS {
+            let pgid = slot.load(Ordering::SeqCst);
+            if pgid != 0 {
+                // SAFETY: kill is async-signal-safe.
+                unsafe {
+                    libc::kill(-pgid, libc::SIGKILL);
+                }
+            }
+        }
+        // Then go down as we would have without the handler.
+        // SAFETY: signal and raise are async-signal-safe.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+/// Polls `child` until it exits, or kills it once `timeout` has passed.
+///
+/// There are no process groups here, so only the child itself is killed.
+#[cfg(not(unix))]
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+    timed_out: &Arc<AtomicBool>,
+) -> io::Result<(ExitStatus, Option<u64>)> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, None));
+        }
+        if std::time::Instant::now() >= deadline {
+            timed_out.store(true, Ordering::SeqCst);
+            child.kill()?;
+            return wait(child);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn wait(child: &mut Child) -> io::Result<(ExitStatus, Option<u64>)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = libc::pid_t::try_from(child.id()).expect("pid fits in pid_t");
+    let mut status = 0;
+    // SAFETY: `rusage` is plain old data, for which all zeroes is valid.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: `status` and `usage` are valid for writes, and `pid` is a
+        // child of ours which has not been waited for yet.
+        let waited = unsafe {
+            libc::wait4(
+                pid,
+                std::ptr::addr_of_mut!(status),
+                0,
+                std::ptr::addr_of_mut!(usage),
+            )
+        };
+        if waited == pid {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    // `ru_maxrss` is in kilobytes on Linux.
+    let peak_rss = u64::try_from(usage.ru_maxrss).ok().map(|kb| kb * 1024);
+    Ok((ExitStatus::from_raw(status), peak_rss))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn wait(child: &mut Child) -> io::Result<(ExitStatus, Option<u64>)> {
+    child.wait().map(|status| (status, None))
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use std::time::Instant;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn measures_child() {
+        let finished = run(
+            Command::new("sh")
+                .args(["-c", "echo out; echo err >&2; exit 3"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(finished.output.status.code(), Some(3));
+        assert_eq!(finished.output.stdout, b"out\n");
+        assert_eq!(finished.output.stderr, b"err\n");
+        assert!(finished.peak_rss.unwrap() > 0);
+        assert!(!finished.timed_out);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn kills_process_group_on_timeout() {
+        let start = Instant::now();
+        // The grandchild keeps stdout open, so reading it only finishes once
+        // the whole group was killed.
+        let finished = run(
+            Command::new("sh")
+                .args(["-c", "sleep 30 & sleep 30"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null()),
+            Some(Duration::from_millis(200)),
+            None,
+        )
+        .unwrap();
+        assert!(finished.timed_out);
+        assert_eq!(finished.output.status.code(), None);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let finished = run(
+            Command::new("sh").args(["-c", "exit 124"]),
+            Some(Duration::from_secs(30)),
+            None,
+        )
+        .unwrap();
+        assert!(!finished.timed_out);
+        assert_eq!(finished.output.status.code(), Some(124));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_output() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reporter = {
+            let reported = Arc::clone(&reported);
+            Reporter::new(move |progress| {
+                if let Progress::TestOutput(output) = progress {
+                    reported.lock().unwrap().extend_from_slice(output);
+                }
+            })
+        };
+        let finished = run(
+            Command::new("sh")
+                .args(["-c", "echo out"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+            None,
+            Some(&reporter),
+        )
+        .unwrap();
+        assert_eq!(finished.output.stdout, b"out\n");
+        assert_eq!(*reported.lock().unwrap(), b"out\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn does_not_wait_for_grandchildren() {
+        for timeout in [None, Some(Duration::from_secs(30))] {
+            let start = Instant::now();
+            // The grandchild keeps stdout open after the leader exited.
+            let finished = run(
+                Command::new("sh")
+                    .args(["-c", "echo out; sleep 30 &"])
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null()),
+                timeout,
+                None,
+            )
+            .unwrap();
+            assert!(!finished.timed_out);
+            assert_eq!(finished.output.status.code(), Some(0));
+            assert_eq!(finished.output.stdout, b"out\n");
+            assert!(start.elapsed() < Duration::from_secs(10));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Running the test command inside a Docker or Podman container, for
+//! reproductions which depend on the system libraries of a specific image.
+//!
+//! The toolchain being tested and the test directory are bind-mounted into the
+//! container at the same paths as on the host, and the toolchain is put first
+//! in `PATH` instead of going through rustup.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::bail;
+use clap::ArgEnum;
+
+/// `PATH` of the container, after the toolchain.
+const SYSTEM_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Engine {
+    Docker,
+    Podman,
+}
+
+impl Engine {
+    fn program(self) -> &'static str {
+        match self {
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+        }
+    }
+
+    fn is_installed(self) -> bool {
+        let status = Command::new(self.program())
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        matches!(status, Ok(status) if status.success())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Container {
+    engine: Engine,
+    image: String,
+}
+
+impl Container {
+    /// Runs tests in `image` with `engine`, or whichever of Docker and Podman
+    /// is installed if not given.
+    pub(crate) fn new(engine: Option<Engine>, image: String) -> anyhow::Result<Container> {
+        let engine = match engine {
+            Some(engine) if engine.is_installed() => engine,
+            Some(engine) => bail!("--container-engine={} is not installed", engine.program()),
+            None => match [Engine::Docker, Engine::Podman]
+                .into_iter()
+                .find(|engine| engine.is_installed())
+            {
+                Some(engine) => engine,
+                None => bail!("--container requires Docker or Podman to be installed"),
+            },
+        };
+        Ok(Container { engine, image })
+    }
+
+    /// A name for a new container, to be able to kill it.
+    pub(crate) fn unique_name() -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        format!(
+            "cargo-bisect-rustc-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Wraps `cmd` to run in a new container called `name`, with the
+    /// toolchain in `toolchain_dir` and the `mounts` available at the same
+    /// paths as on the host.
+    ///
+    /// The environment and working directory of `cmd` are passed on to the
+    /// container, and its program is mounted too if it is a path, such as a
+    /// `--script`.
+    pub(crate) fn wrap(
+        &self,
+        cmd: &Command,
+        name: &str,
+        toolchain_dir: &Path,
+        mounts: &[&Path],
+    ) -> Command {
+        let mut run = Command::new(self.engine.program());
+        run.args(["run", "--rm", "--init", "--name", name]);
+        match self.engine {
+            // Files created in the test directory should belong to us rather
+            // than root. Rootless Podman already maps root to our user.
+            #[cfg(unix)]
+            Engine::Docker => {
+                // SAFETY: these calls cannot fail.
+                let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+                run.arg("--user").arg(format!("{uid}:{gid}"));
+                run.args(["--env", "HOME=/tmp"]);
+            }
+            _ => {}
+        }
+        // Relative paths are relative to our working directory, not that of
+        // the container.
+        let absolute = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        let volume = |path: &Path, options: &str| {
+            let mut volume = path.as_os_str().to_owned();
+            volume.push(":");
+            volume.push(path);
+            volume.push(options);
+            volume
+        };
+        run.arg("--volume").arg(volume(toolchain_dir, ":ro"));
+        for dir in mounts {
+            run.arg("--volume").arg(volume(&absolute(dir), ""));
+        }
+        let mut program = PathBuf::from(cmd.get_program());
+        if program.components().count() > 1 {
+            program = absolute(&program);
+            run.arg("--volume").arg(volume(&program, ":ro"));
+        }
+        if let Some(dir) = cmd.get_current_dir() {
+            run.arg("--workdir").arg(absolute(dir));
+        }
+        let mut path = OsString::from("PATH=");
+        path.push(toolchain_dir.join("bin"));
+        path.push(":");
+        path.push(SYSTEM_PATH);
+        run.arg("--env").arg(path);
+        for (key, value) in cmd.get_envs() {
+            if let Some(value) = value {
+                let mut env = key.to_owned();
+                env.push("=");
+                env.push(value);
+                run.arg("--env").arg(env);
+            }
+        }
+        run.arg(&self.image).arg(program).args(cmd.get_args());
+        run
+    }
+
+    /// Removes the container called `name`, e.g. after the client running it
+    /// was killed by `--timeout`.
+    pub(crate) fn kill(&self, name: &str) {
+        let _ = Command::new(self.engine.program())
+            .args(["rm", "--force", name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn wraps_command() {
+        let container = Container {
+            engine: Engine::Podman,
+            image: "rust:1-bullseye".to_string(),
+        };
+        let mut cmd = Command::new("cargo");
+        cmd.arg("build")
+            .current_dir("/work/foo")
+            .env("CARGO_TARGET_DIR", "target-nightly-2022-08-01");
+        let wrapped = container.wrap(
+            &cmd,
+            "test",
+            Path::new("/home/me/.rustup/toolchains/nightly-2022-08-01"),
+            &[Path::new("/work/foo")],
+        );
+        assert_eq!(wrapped.get_program(), "podman");
+        let args: Vec<&OsStr> = wrapped.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "--init",
+                "--name",
+                "test",
+                "--volume",
+                "/home/me/.rustup/toolchains/nightly-2022-08-01:\
+                 /home/me/.rustup/toolchains/nightly-2022-08-01:ro",
+                "--volume",
+                "/work/foo:/work/foo",
+                "--workdir",
+                "/work/foo",
+                "--env",
+                "PATH=/home/me/.rustup/toolchains/nightly-2022-08-01/bin:\
+                 /usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                "--env",
+                "CARGO_TARGET_DIR=target-nightly-2022-08-01",
+                "rust:1-bullseye",
+                "cargo",
+                "build",
+            ]
+        );
+    }
+}
@@ -13,7 +13,7 @@ use git2::build::RepoBuilder;
 use git2::{Commit as Git2Commit, Repository};
 use log::debug;
 
-use crate::Commit;
+use crate::{Commit, Reporter};
 
 impl Commit {
     // Takes &mut because libgit2 internally caches summaries
@@ -61,15 +61,15 @@ fn lookup_rev<'rev>(repo: &'rev RustcRepo, rev: &str) -> anyhow::Result<Git2Comm
     bail!("Could not find a commit for revision specifier '{}'", rev)
 }
 
-fn get_repo() -> anyhow::Result<RustcRepo> {
-    fn open(path: &Path) -> anyhow::Result<(Repository, String)> {
-        eprintln!("opening existing repository at {:?}", path);
+fn get_repo(reporter: &Reporter) -> anyhow::Result<RustcRepo> {
+    let open = |path: &Path| -> anyhow::Result<(Repository, String)> {
+        status!(reporter, "opening existing repository at {:?}", path);
         let repo = Repository::open(path)?;
 
         let origin_remote = find_origin_remote(&repo)?;
-        eprintln!("Found origin remote under name `{origin_remote}`");
+        status!(reporter, "Found origin remote under name `{origin_remote}`");
 
-        eprintln!("refreshing repository at {:?}", path);
+        status!(reporter, "refreshing repository at {:?}", path);
         // This uses the CLI because libgit2 is quite slow to fetch a large repository.
         let status = std::process::Command::new("git")
             .arg("fetch")
@@ -82,7 +82,7 @@ fn get_repo() -> anyhow::Result<RustcRepo> {
         }
 
         Ok((repo, origin_remote))
-    }
+    };
 
     let loc = Path::new("rust.git");
     let (repository, origin_remote) = match (env::var_os("RUST_SRC_REPO"), RUST_SRC_REPO) {
@@ -90,7 +90,7 @@ fn get_repo() -> anyhow::Result<RustcRepo> {
         (None, _) if loc.exists() => open(loc),
         (None, Some(repo)) => open(Path::new(repo)),
         _ => {
-            eprintln!("cloning rust repository");
+            status!(reporter, "cloning rust repository");
             Ok((
                 RepoBuilder::new().bare(true).clone(RUST_SRC_URL, loc)?,
                 "origin".to_string(),
@@ -119,19 +119,23 @@ Try adding a remote pointing to `{RUST_SRC_URL}` in the rust repository at `{}`.
         })
 }
 
-pub(crate) fn get_commit(sha: &str) -> anyhow::Result<Commit> {
-    let repo = get_repo()?;
+pub(crate) fn get_commit(sha: &str, reporter: &Reporter) -> anyhow::Result<Commit> {
+    let repo = get_repo(reporter)?;
     let mut rev = lookup_rev(&repo, sha)?;
     Ok(Commit::from_git2_commit(&mut rev))
 }
 
 /// Returns the bors merge commits between the two specified boundaries
 /// (boundaries inclusive).
-pub fn get_commits_between(first_commit: &str, last_commit: &str) -> anyhow::Result<Vec<Commit>> {
-    let repo = get_repo()?;
-    eprintln!("looking up first commit");
+pub fn get_commits_between(
+    first_commit: &str,
+    last_commit: &str,
+    reporter: &Reporter,
+) -> anyhow::Result<Vec<Commit>> {
+    let repo = get_repo(reporter)?;
+    status!(reporter, "looking up first commit");
     let mut first = lookup_rev(&repo, first_commit)?;
-    eprintln!("looking up second commit");
+    status!(reporter, "looking up second commit");
     let last = lookup_rev(&repo, last_commit)?;
 
     // Sanity check -- our algorithm below only works reliably if the
@@ -144,7 +148,10 @@ pub fn get_commits_between(first_commit: &str, last_commit: &str) -> anyhow::Res
         }
     };
 
-    eprintln!("checking that commits are by bors and thus have ci artifacts...");
+    status!(
+        reporter,
+        "checking that commits are by bors and thus have ci artifacts..."
+    );
     assert_by_bors(&first)?;
     assert_by_bors(&last)?;
     // Now find the commits
@@ -152,7 +159,7 @@ pub fn get_commits_between(first_commit: &str, last_commit: &str) -> anyhow::Res
     // to only get merge commits.
     // This uses the fact that all bors merge commits have the earlier
     // merge commit as their first parent.
-    eprintln!("finding bors merge commits");
+    status!(reporter, "finding bors merge commits");
     let mut res = Vec::new();
     let mut current = last;
     loop {
@@ -181,7 +188,8 @@ pub fn get_commits_between(first_commit: &str, last_commit: &str) -> anyhow::Res
     res.push(Commit::from_git2_commit(&mut first));
     // Reverse in order to obtain chronological order
     res.reverse();
-    eprintln!(
+    status!(
+        reporter,
         "found {} bors merge commits in the specified range",
         res.len()
     );
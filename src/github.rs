@@ -3,7 +3,7 @@ use reqwest::{self, blocking::Client, blocking::Response};
 use reqwest::header::{HeaderMap, InvalidHeaderValue, HeaderValue, USER_AGENT, AUTHORIZATION};
 use serde::{Deserialize, Serialize};
 
-use crate::{Commit, GitDate, parse_to_utc_date};
+use crate::{Commit, GitDate, Reporter, parse_to_utc_date};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct GithubCommitComparison {
@@ -46,22 +46,27 @@ impl GithubCommitElem {
     }
 }
 
-fn headers() -> Result<HeaderMap, InvalidHeaderValue> {
+fn headers(reporter: &Reporter) -> Result<HeaderMap, InvalidHeaderValue> {
     let mut headers = HeaderMap::new();
     let user_agent = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
     let user_agent = HeaderValue::from_static(user_agent);
     headers.insert(USER_AGENT, user_agent);
     if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        eprintln!("adding local env GITHUB_TOKEN value to headers in github query");
+        status!(
+            reporter,
+            "adding local env GITHUB_TOKEN value to headers in github query"
+        );
         let value = HeaderValue::from_str(&format!("token {token}"))?;
         headers.insert(AUTHORIZATION, value);
     }
     Ok(headers)
 }
 
-pub(crate) fn get_commit(sha: &str) -> anyhow::Result<Commit> {
+pub(crate) fn get_commit(sha: &str, reporter: &Reporter) -> anyhow::Result<Commit> {
     let url = CommitDetailsUrl { sha }.url();
-    let client = Client::builder().default_headers(headers()?).build()?;
+    let client = Client::builder()
+        .default_headers(headers(reporter)?)
+        .build()?;
     let response: Response = client.get(&url).send()?;
     let status = response.status();
     if !status.is_success() {
@@ -89,7 +94,7 @@ pub(crate) struct CommitsQuery<'a> {
 /// (boundaries inclusive).
 
 impl CommitsQuery<'_> {
-    pub fn get_commits(&self) -> anyhow::Result<Vec<Commit>> {
+    pub fn get_commits(&self, reporter: &Reporter) -> anyhow::Result<Vec<Commit>> {
         // build up commit sequence, by feeding in `sha` as the starting point, and
         // working way backwards to max(`self.since_date`, `self.earliest_sha`).
         let mut commits = Vec::new();
@@ -97,7 +102,9 @@ impl CommitsQuery<'_> {
         // focus on Pull Request merges, all authored and committed by bors.
         let author = "bors";
 
-        let client = Client::builder().default_headers(headers()?).build()?;
+        let client = Client::builder()
+            .default_headers(headers(reporter)?)
+            .build()?;
         for page in 1.. {
             let url = CommitsUrl {
                 page,
@@ -117,7 +124,8 @@ impl CommitsQuery<'_> {
                 commits.push(commit);
 
                 Ok(if elem.sha == self.earliest_sha {
-                    eprintln!(
+                    status!(
+                        reporter,
                         "ending github query because we found starting sha: {}",
                         elem.sha
                     );
@@ -132,7 +140,8 @@ impl CommitsQuery<'_> {
             }
         }
 
-        eprintln!(
+        status!(
+            reporter,
             "get_commits_between returning commits, len: {}",
             commits.len()
         );
@@ -225,7 +234,11 @@ mod tests {
 
     #[test]
     fn test_github() {
-        let c = get_commit("25674202bb7415e0c0ecd07856749cfb7f591be6").unwrap();
+        let c = get_commit(
+            "25674202bb7415e0c0ecd07856749cfb7f591be6",
+            &Reporter::new(|_| {}),
+        )
+        .unwrap();
         let expected_c = Commit { sha: "25674202bb7415e0c0ecd07856749cfb7f591be6".to_string(), 
                                 date: parse_to_utc_date("2022-05-04").unwrap(), 
                                 summary: "Auto merge of #96695 - JohnTitor:rollup-oo4fc1h, r=JohnTitor\n\nRollup of 6 pull requests\n\nSuccessful merges:\n\n - #96597 (openbsd: unbreak build on native platform)\n - #96662 (Fix typo in lint levels doc)\n - #96668 (Fix flaky rustdoc-ui test because it did not replace time result)\n - #96679 (Quick fix for #96223.)\n - #96684 (Update `ProjectionElem::Downcast` documentation)\n - #96686 (Add some TAIT-related tests)\n\nFailed merges:\n\nr? `@ghost`\n`@rustbot` modify labels: rollup".to_string()
@@ -1,79 +1,427 @@
+//! Searching a range for the first element which satisfies a predicate, such
+//! as the first toolchain with a regression.
+//!
+//! Every [`Strategy`] presumes that the first element does not satisfy the
+//! predicate and that the last one does, and tests the elements in between
+//! through a [`Probe`].
+
 use std::collections::BTreeMap;
 use std::fmt;
 
-pub fn least_satisfying<T, P>(slice: &[T], mut predicate: P) -> usize
-where
-    T: fmt::Display + fmt::Debug,
-    P: FnMut(&T, usize, usize) -> Satisfies,
-{
-    let mut cache = BTreeMap::new();
-    let mut predicate = |idx: usize, rm_no, lm_yes| {
-        let range = lm_yes - rm_no + 1;
-        // FIXME: This does not consider unknown_ranges.
-        let remaining = range / 2;
-        let estimate = estimate_steps(range);
-        *cache
-            .entry(idx)
-            .or_insert_with(|| predicate(&slice[idx], remaining, estimate))
-    };
-    let mut unknown_ranges: Vec<(usize, usize)> = Vec::new();
-    // presume that the slice starts with a no
-    // this should be tested before call
-    let mut rm_no = 0;
+use serde::{Deserialize, Serialize};
 
-    // presume that the slice ends with a yes
-    // this should be tested before the call
-    let mut lm_yes = slice.len() - 1;
+/// A way of searching for the first element which satisfies a predicate.
+pub trait Strategy {
+    /// Finds the first of `len` elements which satisfies the predicate,
+    /// testing them with `probe`.
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate;
+}
 
-    let mut next = (rm_no + lm_yes) / 2;
+/// Tests the elements of a search for a [`Strategy`].
+pub trait Probe {
+    /// Tests the elements at `indices`, possibly at the same time, and
+    /// returns whether each one satisfies the predicate, in the same order.
+    fn test(&mut self, indices: &[usize], remaining: &Remaining) -> Vec<Satisfies>;
 
-    loop {
-        // simple case with no unknown ranges
-        if rm_no + 1 == lm_yes {
-            return lm_yes;
-        }
-        for (left, right) in unknown_ranges.iter().copied() {
-            // if we're straddling an unknown range, then pretend it doesn't exist
-            if rm_no + 1 == left && right + 1 == lm_yes {
-                return lm_yes;
+    /// Tests the element at `index` repeatedly, for predicates which only
+    /// report `Satisfies::Yes` some of the time, or returns `None` if it
+    /// could not be tested. By default, it is tested once.
+    fn test_trials(&mut self, index: usize, remaining: &Remaining) -> Option<Trials> {
+        match self.test(&[index], remaining)[0] {
+            Satisfies::Yes => Some(Trials {
+                regressed: 1,
+                baseline: 0,
+            }),
+            Satisfies::No => Some(Trials {
+                regressed: 0,
+                baseline: 1,
+            }),
+            Satisfies::Unknown => None,
+        }
+    }
+
+    /// Called before testing the element at `next` with those which may be
+    /// tested in the step after, depending on its result, so that they can be
+    /// prepared in the meantime.
+    fn prefetch(&mut self, _next: usize, _candidates: &[usize]) {}
+}
+
+/// How much of a search is left, for reporting progress.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Remaining {
+    /// Number of elements which may still have to be tested after this step.
+    pub elements: usize,
+    /// Rough number of steps after this one.
+    pub steps: usize,
+    /// For probabilistic searches, the confidence in the most likely
+    /// regression point so far.
+    pub confidence: Option<f64>,
+}
+
+/// Plain bisection, which tests the neighbours of untestable elements until
+/// it finds testable ones.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Bisection;
+
+impl Strategy for Bisection {
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate {
+        let mut cache = BTreeMap::new();
+        let mut predicate = |probe: &mut dyn Probe,
+                             unknown_ranges: &[(usize, usize)],
+                             idx: usize,
+                             rm_no: usize,
+                             lm_yes: usize| {
+            // The elements of the unknown ranges between the bounds are not
+            // tested again.
+            let unknown: usize = unknown_ranges
+                .iter()
+                .map(|&(left, right)| {
+                    (right.min(lm_yes - 1) + 1).saturating_sub(left.max(rm_no + 1))
+                })
+                .sum();
+            let range = lm_yes - rm_no + 1 - unknown;
+            let remaining = Remaining {
+                elements: range / 2,
+                steps: estimate_steps(range),
+                confidence: None,
+            };
+            *cache
+                .entry(idx)
+                .or_insert_with(|| probe.test(&[idx], &remaining)[0])
+        };
+        let mut unknown_ranges: Vec<(usize, usize)> = Vec::new();
+        // presume that the slice starts with a no
+        // this should be tested before call
+        let mut rm_no = 0;
+
+        // presume that the slice ends with a yes
+        // this should be tested before the call
+        let mut lm_yes = len - 1;
+
+        let mut next = (rm_no + lm_yes) / 2;
+
+        loop {
+            // simple case with no unknown ranges
+            if rm_no + 1 == lm_yes {
+                return Estimate::certain(lm_yes);
+            }
+            for (left, right) in unknown_ranges.iter().copied() {
+                // if we're straddling an unknown range, then pretend it doesn't exist
+                if rm_no + 1 == left && right + 1 == lm_yes {
+                    return Estimate::certain(lm_yes);
+                }
+                // check if we're checking inside an unknown range and set the next check outside of it
+                if left <= next && next <= right {
+                    if rm_no < left - 1 {
+                        next = left - 1;
+                    } else if right < lm_yes {
+                        next = right + 1;
+                    }
+                    break;
+                }
             }
-            // check if we're checking inside an unknown range and set the next check outside of it
-            if left <= next && next <= right {
-                if rm_no < left - 1 {
-                    next = left - 1;
-                } else if right < lm_yes {
-                    next = right + 1;
+
+            let candidates: Vec<usize> = [rm_no + (next - rm_no) / 2, next + (lm_yes - next) / 2]
+                .into_iter()
+                .filter(|&i| rm_no < i && i < lm_yes && i != next)
+                .collect();
+            probe.prefetch(next, &candidates);
+
+            let r = predicate(probe, &unknown_ranges, next, rm_no, lm_yes);
+            match r {
+                Satisfies::Yes => {
+                    lm_yes = next;
+                    next = (rm_no + lm_yes) / 2;
+                }
+                Satisfies::No => {
+                    rm_no = next;
+                    next = (rm_no + lm_yes) / 2;
+                }
+                Satisfies::Unknown => {
+                    let mut left = next;
+                    while left > 0
+                        && predicate(probe, &unknown_ranges, left, rm_no, lm_yes)
+                            == Satisfies::Unknown
+                    {
+                        left -= 1;
+                    }
+                    let mut right = next;
+                    while right + 1 < len
+                        && predicate(probe, &unknown_ranges, right, rm_no, lm_yes)
+                            == Satisfies::Unknown
+                    {
+                        right += 1;
+                    }
+                    unknown_ranges.push((left + 1, right - 1));
+                    next = left;
                 }
-                break;
             }
         }
+    }
+}
 
-        let r = predicate(next, rm_no, lm_yes);
-        match r {
-            Satisfies::Yes => {
-                lm_yes = next;
-                next = (rm_no + lm_yes) / 2;
+/// Bisection over the untested elements between the last known no and the
+/// first known yes, which leaves the untestable elements out both when picking
+/// the next one and when estimating the steps left.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UnknownAwareBisection;
+
+impl Strategy for UnknownAwareBisection {
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate {
+        split_search(len, 1, probe)
+    }
+}
+
+/// Tests up to `jobs` elements at a time, e.g. on separate threads, splitting
+/// the untested elements into `jobs + 1` parts at each step rather than in
+/// half.
+#[derive(Copy, Clone, Debug)]
+pub struct KArySearch {
+    pub jobs: usize,
+}
+
+impl Strategy for KArySearch {
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate {
+        split_search(len, self.jobs, probe)
+    }
+}
+
+/// Tests the elements one after the other from the start, which takes as many
+/// steps as there are elements before the first yes, but finds it even if the
+/// predicate is not monotonic.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LinearScan;
+
+impl Strategy for LinearScan {
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate {
+        for i in 1..len.saturating_sub(1) {
+            if i + 1 < len - 1 {
+                probe.prefetch(i, &[i + 1]);
             }
-            Satisfies::No => {
-                rm_no = next;
-                next = (rm_no + lm_yes) / 2;
+            let left = len - 2 - i;
+            let remaining = Remaining {
+                elements: left,
+                steps: left,
+                confidence: None,
+            };
+            if probe.test(&[i], &remaining)[0] == Satisfies::Yes {
+                return Estimate::certain(i);
             }
-            Satisfies::Unknown => {
-                let mut left = next;
-                while left > 0 && predicate(left, rm_no, lm_yes) == Satisfies::Unknown {
-                    left -= 1;
-                }
-                let mut right = next;
-                while right + 1 < slice.len()
-                    && predicate(right, rm_no, lm_yes) == Satisfies::Unknown
-                {
-                    right += 1;
-                }
-                unknown_ranges.push((left + 1, right - 1));
-                next = left;
+        }
+        Estimate::certain(len - 1)
+    }
+}
+
+/// Searches with repeated trials, for predicates which only report
+/// `Satisfies::Yes` some of the time.
+///
+/// The next element to probe is chosen from the posterior distribution of the
+/// regression point given all trials so far. The search stops once the most
+/// likely index reaches `confidence`, or with a lower confidence after a
+/// bounded number of probes.
+#[derive(Copy, Clone, Debug)]
+pub struct ProbabilisticSearch {
+    pub confidence: f64,
+}
+
+impl Strategy for ProbabilisticSearch {
+    fn search(&self, len: usize, probe: &mut dyn Probe) -> Estimate {
+        if len <= 2 {
+            return Estimate::certain(len - 1);
+        }
+        let mut observed = vec![Trials::default(); len];
+        let mut unknown = vec![false; len];
+        let max_probes = PROBES_PER_STEP * (estimate_steps(len) + 1);
+
+        for probes in 0..max_probes {
+            let posterior = posterior(&observed);
+            let best = best_estimate(&posterior, &unknown);
+            if best.confidence >= self.confidence {
+                return best;
+            }
+            let remaining = Remaining {
+                elements: (1..len - 1)
+                    .filter(|&i| !unknown[i] && observed[i] == Trials::default())
+                    .count(),
+                steps: max_probes - probes - 1,
+                confidence: Some(best.confidence),
+            };
+            match next_probe(&posterior, &unknown) {
+                Some(next) => match probe.test_trials(next, &remaining) {
+                    Some(trials) => observed[next].add(trials),
+                    None => unknown[next] = true,
+                },
+                None => return best,
+            }
+        }
+
+        best_estimate(&posterior(&observed), &unknown)
+    }
+}
+
+/// Splits the untested elements between the last known no and the first known
+/// yes into `jobs + 1` parts at each step, and tests the elements at the
+/// boundaries.
+///
+/// A regression inside a range of `Satisfies::Unknown` elements is attributed
+/// to the first known yes after it.
+///
+/// # Panics
+///
+/// Panics if `probe` does not return one result per element.
+fn split_search(len: usize, jobs: usize, probe: &mut dyn Probe) -> Estimate {
+    let mut results = vec![None; len];
+    results[0] = Some(Satisfies::No);
+    results[len - 1] = Some(Satisfies::Yes);
+
+    loop {
+        let lm_yes = results
+            .iter()
+            .position(|r| *r == Some(Satisfies::Yes))
+            .unwrap();
+        // A no after the first yes contradicts the presumption that the
+        // predicate is monotonic, and is ignored.
+        let rm_no = results[..lm_yes]
+            .iter()
+            .rposition(|r| *r == Some(Satisfies::No))
+            .unwrap();
+        let untested: Vec<usize> = (rm_no + 1..lm_yes)
+            .filter(|&i| results[i].is_none())
+            .collect();
+        if untested.is_empty() {
+            return Estimate::certain(lm_yes);
+        }
+
+        let k = jobs.min(untested.len());
+        let probes: Vec<usize> = (1..=k)
+            .map(|j| untested[j * untested.len() / (k + 1)])
+            .collect();
+        if let [next] = probes[..] {
+            let (left, right) = untested.split_at(untested.len() / 2);
+            let candidates: Vec<usize> = [left, &right[1..]]
+                .iter()
+                .filter_map(|part| part.get(part.len() / 2))
+                .copied()
+                .collect();
+            probe.prefetch(next, &candidates);
+        }
+
+        // The elements found to be untestable are not counted, since they are
+        // never tested again.
+        let left = untested.len() / (k + 1);
+        let remaining = Remaining {
+            elements: left,
+            steps: steps_to_split(left, k + 1),
+            confidence: None,
+        };
+        let verdicts = probe.test(&probes, &remaining);
+        assert_eq!(verdicts.len(), probes.len());
+        for (i, r) in probes.into_iter().zip(verdicts) {
+            results[i] = Some(r);
+        }
+    }
+}
+
+/// Number of steps needed in the worst case to search `untested` elements,
+/// splitting them into `parts` at each step.
+fn steps_to_split(untested: usize, parts: usize) -> usize {
+    let mut steps = 0;
+    let mut searched = 1;
+    while searched <= untested {
+        searched *= parts;
+        steps += 1;
+    }
+    steps
+}
+
+/// Probability that testing a toolchain without the regression reports it
+/// anyway, used by [`ProbabilisticSearch`].
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// How many probes [`ProbabilisticSearch`] may run per step that a plain
+/// bisection would need, before giving up on reaching the requested confidence.
+const PROBES_PER_STEP: usize = 8;
+
+/// Resolution of the grid over which the detection rate of a flaky predicate
+/// is marginalized.
+const DETECTION_RATE_STEPS: u8 = 20;
+
+/// Returns the probability of each index being the first one that satisfies
+/// the predicate, given the trials observed so far.
+///
+/// The rate at which satisfying elements are detected is not known, so it is
+/// marginalized over a uniform prior.
+fn posterior(observed: &[Trials]) -> Vec<f64> {
+    let good = |t: &Trials| t.log_likelihood(FALSE_POSITIVE_RATE);
+    let log_likelihoods: Vec<Vec<f64>> = (1..DETECTION_RATE_STEPS)
+        .map(|step| {
+            let detection_rate = f64::from(step) / f64::from(DETECTION_RATE_STEPS);
+            let bad = |t: &Trials| t.log_likelihood(detection_rate);
+            // The first element is presumed to not satisfy the predicate.
+            let mut ll = observed.iter().skip(1).map(bad).sum::<f64>() + good(&observed[0]);
+            let mut log_likelihood = vec![f64::NEG_INFINITY];
+            for t in &observed[1..observed.len() - 1] {
+                log_likelihood.push(ll);
+                ll += good(t) - bad(t);
             }
+            log_likelihood.push(ll);
+            log_likelihood
+        })
+        .collect();
+
+    let max = log_likelihoods
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let mut weights = vec![0.0; observed.len()];
+    for log_likelihood in &log_likelihoods {
+        for (w, ll) in weights.iter_mut().zip(log_likelihood) {
+            *w += (ll - max).exp();
         }
     }
+    let total: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+/// Returns the most likely regression point. As in [`Bisection`], a
+/// regression inside a range of untestable elements is attributed to the first
+/// testable element after it.
+fn best_estimate(posterior: &[f64], unknown: &[bool]) -> Estimate {
+    let mut folded = posterior.to_vec();
+    for i in 1..folded.len() - 1 {
+        if unknown[i] {
+            folded[i + 1] += folded[i];
+            folded[i] = 0.0;
+        }
+    }
+    let (index, &confidence) = folded
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap();
+    Estimate { index, confidence }
+}
+
+/// Picks the testable index which splits the posterior most evenly.
+fn next_probe(posterior: &[f64], unknown: &[bool]) -> Option<usize> {
+    let mut cdf = 0.0;
+    let mut best: Option<(usize, f64)> = None;
+    for (i, p) in posterior.iter().enumerate() {
+        cdf += p;
+        if i == 0 || unknown[i] {
+            continue;
+        }
+        let distance = (cdf - 0.5).abs();
+        let closer = match best {
+            Some((_, d)) => distance < d,
+            None => true,
+        };
+        if closer {
+            best = Some((i, distance));
+        }
+    }
+    best.map(|(i, _)| i)
 }
 
 fn estimate_steps(range: usize) -> usize {
@@ -102,8 +450,48 @@ fn estimate_steps(range: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::Satisfies::{No, Unknown, Yes};
-    use super::{estimate_steps, least_satisfying, Satisfies};
+    use super::{
+        estimate_steps, Bisection, KArySearch, LinearScan, ProbabilisticSearch, Probe, Remaining,
+        Satisfies, Strategy, Trials, UnknownAwareBisection,
+    };
     use quickcheck::{QuickCheck, TestResult};
+    use std::ops::RangeInclusive;
+
+    fn strategies() -> Vec<Box<dyn Strategy>> {
+        vec![
+            Box::new(Bisection),
+            Box::new(UnknownAwareBisection),
+            Box::new(LinearScan),
+            Box::new(KArySearch { jobs: 3 }),
+            Box::new(ProbabilisticSearch { confidence: 0.95 }),
+        ]
+    }
+
+    /// Answers from a slice of results, and records what it was asked.
+    #[derive(Default)]
+    struct Recorder<'a> {
+        results: &'a [Satisfies],
+        tested: Vec<usize>,
+        remaining: Vec<Remaining>,
+        largest_batch: usize,
+    }
+
+    impl Probe for Recorder<'_> {
+        fn test(&mut self, indices: &[usize], remaining: &Remaining) -> Vec<Satisfies> {
+            self.tested.extend(indices);
+            self.largest_batch = self.largest_batch.max(indices.len());
+            self.remaining.push(*remaining);
+            indices.iter().map(|&i| self.results[i]).collect()
+        }
+    }
+
+    fn search(strategy: &dyn Strategy, results: &[Satisfies]) -> usize {
+        let mut probe = Recorder {
+            results,
+            ..Recorder::default()
+        };
+        strategy.search(results.len(), &mut probe).index
+    }
 
     fn prop(xs: Vec<Option<bool>>) -> TestResult {
         let mut satisfies_v = xs
@@ -122,48 +510,61 @@ mod tests {
             }
         }
 
-        let res = least_satisfying(&satisfies_v, |i, _, _| *i);
         let exp = first_yes.unwrap();
-        TestResult::from_bool(res == exp)
+        TestResult::from_bool(
+            strategies()
+                .iter()
+                .all(|strategy| search(&**strategy, &satisfies_v) == exp),
+        )
+    }
+
+    fn prop_parallel(xs: Vec<bool>, jobs: u8) -> TestResult {
+        let jobs = usize::from(jobs % 8) + 1;
+        let mut satisfies_v: Vec<Satisfies> = xs.into_iter().map(|x| Some(x).into()).collect();
+        satisfies_v.insert(0, Satisfies::No);
+        satisfies_v.push(Satisfies::Yes);
+        satisfies_v.sort_by_key(|&s| s == Satisfies::Yes);
+
+        let exp = satisfies_v.iter().position(|&s| s == Satisfies::Yes);
+        let mut probe = Recorder {
+            results: &satisfies_v,
+            ..Recorder::default()
+        };
+        let res = KArySearch { jobs }.search(satisfies_v.len(), &mut probe);
+        TestResult::from_bool(Some(res.index) == exp && probe.largest_batch <= jobs)
     }
 
     #[test]
     fn least_satisfying_1() {
-        assert_eq!(
-            least_satisfying(&[No, Unknown, Unknown, No, Yes], |i, _, _| *i),
-            4
-        );
+        assert_eq!(search(&Bisection, &[No, Unknown, Unknown, No, Yes]), 4);
     }
 
     #[test]
     fn least_satisfying_2() {
-        assert_eq!(
-            least_satisfying(&[No, Unknown, Yes, Unknown, Yes], |i, _, _| *i),
-            2
-        );
+        assert_eq!(search(&Bisection, &[No, Unknown, Yes, Unknown, Yes]), 2);
     }
 
     #[test]
     fn least_satisfying_3() {
-        assert_eq!(least_satisfying(&[No, No, No, No, Yes], |i, _, _| *i), 4);
+        assert_eq!(search(&Bisection, &[No, No, No, No, Yes]), 4);
     }
 
     #[test]
     fn least_satisfying_4() {
-        assert_eq!(least_satisfying(&[No, No, Yes, Yes, Yes], |i, _, _| *i), 2);
+        assert_eq!(search(&Bisection, &[No, No, Yes, Yes, Yes]), 2);
     }
 
     #[test]
     fn least_satisfying_5() {
-        assert_eq!(least_satisfying(&[No, Yes, Yes, Yes, Yes], |i, _, _| *i), 1);
+        assert_eq!(search(&Bisection, &[No, Yes, Yes, Yes, Yes]), 1);
     }
 
     #[test]
     fn least_satisfying_6() {
         assert_eq!(
-            least_satisfying(
-                &[No, Yes, Yes, Unknown, Unknown, Yes, Unknown, Yes],
-                |i, _, _| *i
+            search(
+                &Bisection,
+                &[No, Yes, Yes, Unknown, Unknown, Yes, Unknown, Yes]
             ),
             1
         );
@@ -171,22 +572,255 @@ mod tests {
 
     #[test]
     fn least_satisfying_7() {
-        assert_eq!(least_satisfying(&[No, Yes, Unknown, Yes], |i, _, _| *i), 1);
+        assert_eq!(search(&Bisection, &[No, Yes, Unknown, Yes]), 1);
     }
 
     #[test]
     fn least_satisfying_8() {
         assert_eq!(
-            least_satisfying(&[No, Unknown, No, No, Unknown, Yes, Yes], |i, _, _| *i),
+            search(&Bisection, &[No, Unknown, No, No, Unknown, Yes, Yes]),
             5
         );
     }
 
+    /// Checks that every element tested was prefetched by the step before.
+    #[derive(Default)]
+    struct Prefetched {
+        /// The candidates prefetched by the previous step, and this one.
+        candidates: (Option<Vec<usize>>, Option<Vec<usize>>),
+        steps: usize,
+    }
+
+    impl Probe for Prefetched {
+        fn test(&mut self, indices: &[usize], _: &Remaining) -> Vec<Satisfies> {
+            self.steps += 1;
+            for i in indices {
+                if let Some(previous) = &self.candidates.0 {
+                    assert!(previous.contains(i), "{i} was not prefetched");
+                }
+            }
+            indices.iter().map(|&i| Some(i >= 37).into()).collect()
+        }
+
+        fn prefetch(&mut self, _: usize, candidates: &[usize]) {
+            self.candidates.0 = self.candidates.1.replace(candidates.to_vec());
+        }
+    }
+
+    #[test]
+    fn least_satisfying_prefetches_next_steps() {
+        for strategy in [&Bisection as &dyn Strategy, &UnknownAwareBisection] {
+            let mut probe = Prefetched::default();
+            assert_eq!(strategy.search(100, &mut probe).index, 37);
+            assert!(probe.steps > 1);
+        }
+    }
+
+    fn in_parallel(slice: &[Satisfies], jobs: usize) -> usize {
+        search(&KArySearch { jobs }, slice)
+    }
+
+    #[test]
+    fn least_satisfying_in_parallel_unknown() {
+        for jobs in 1..=4 {
+            assert_eq!(in_parallel(&[No, Unknown, Unknown, No, Yes], jobs), 4);
+            assert_eq!(in_parallel(&[No, Unknown, Yes, Unknown, Yes], jobs), 2);
+            assert_eq!(
+                in_parallel(&[No, Unknown, No, No, Unknown, Yes, Yes], jobs),
+                5
+            );
+        }
+    }
+
+    #[test]
+    fn least_satisfying_in_parallel_steps() {
+        let slice: Vec<Satisfies> = (0..100).map(|i| Some(i >= 37).into()).collect();
+        let mut probe = Recorder {
+            results: &slice,
+            ..Recorder::default()
+        };
+        assert_eq!(
+            KArySearch { jobs: 7 }.search(slice.len(), &mut probe).index,
+            37
+        );
+        let steps = probe.remaining.len();
+        // Each step narrows the range eightfold, rather than in half.
+        assert!(steps <= 3, "took {steps} steps");
+    }
+
+    /// Deterministically simulates a flaky predicate: every element from
+    /// `first_yes` on satisfies it on every `period`-th trial, and the
+    /// elements in `untestable` cannot be tested.
+    struct Flaky {
+        first_yes: usize,
+        period: u32,
+        untestable: RangeInclusive<usize>,
+        runs: u32,
+    }
+
+    impl Flaky {
+        fn new(first_yes: usize, period: u32) -> Flaky {
+            Flaky {
+                first_yes,
+                period,
+                #[allow(clippy::reversed_empty_ranges)]
+                untestable: 1..=0,
+                runs: 0,
+            }
+        }
+    }
+
+    impl Probe for Flaky {
+        fn test(&mut self, indices: &[usize], remaining: &Remaining) -> Vec<Satisfies> {
+            indices
+                .iter()
+                .map(|&i| match self.test_trials(i, remaining) {
+                    Some(trials) => trials.satisfies(),
+                    None => Unknown,
+                })
+                .collect()
+        }
+
+        fn test_trials(&mut self, index: usize, _: &Remaining) -> Option<Trials> {
+            if self.untestable.contains(&index) {
+                return None;
+            }
+            let mut trials = Trials::default();
+            for _ in 0..4 {
+                self.runs += 1;
+                if index >= self.first_yes && self.runs % self.period == 0 {
+                    trials.regressed += 1;
+                } else {
+                    trials.baseline += 1;
+                }
+            }
+            Some(trials)
+        }
+    }
+
+    const PROBABILISTIC: ProbabilisticSearch = ProbabilisticSearch { confidence: 0.95 };
+
+    #[test]
+    fn most_likely_satisfying_deterministic() {
+        let estimate = PROBABILISTIC.search(50, &mut Flaky::new(17, 1));
+        assert_eq!(estimate.index, 17);
+        assert!(estimate.confidence >= 0.95);
+    }
+
+    #[test]
+    fn most_likely_satisfying_flaky() {
+        let estimate = PROBABILISTIC.search(50, &mut Flaky::new(33, 3));
+        assert_eq!(estimate.index, 33);
+        assert!(estimate.confidence >= 0.95);
+    }
+
+    #[test]
+    fn most_likely_satisfying_unknown() {
+        let mut probe = Flaky {
+            untestable: 6..=8,
+            ..Flaky::new(8, 1)
+        };
+        assert_eq!(PROBABILISTIC.search(20, &mut probe).index, 9);
+    }
+
+    #[test]
+    fn strategies_find_the_first_yes() {
+        for strategy in strategies() {
+            let strategy = &*strategy;
+            assert_eq!(search(strategy, &[No, Unknown, Unknown, No, Yes]), 4);
+            assert_eq!(search(strategy, &[No, Unknown, Yes, Unknown, Yes]), 2);
+            assert_eq!(search(strategy, &[No, Yes, Unknown, Yes]), 1);
+            assert_eq!(search(strategy, &[No, Yes]), 1);
+        }
+    }
+
+    #[test]
+    fn linear_scan_tests_in_order() {
+        let results: Vec<Satisfies> = (0..10).map(|i| Some(i >= 4).into()).collect();
+        let mut probe = Recorder {
+            results: &results,
+            ..Recorder::default()
+        };
+        assert_eq!(LinearScan.search(results.len(), &mut probe).index, 4);
+        assert_eq!(probe.tested, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unknown_aware_estimates_leave_out_unknowns() {
+        // Half of the range cannot be tested.
+        let results: Vec<Satisfies> = (0..34)
+            .map(|i| match i {
+                0 => No,
+                1..=16 => Unknown,
+                _ => Yes,
+            })
+            .collect();
+        let mut probe = Recorder {
+            results: &results,
+            ..Recorder::default()
+        };
+        assert_eq!(
+            UnknownAwareBisection
+                .search(results.len(), &mut probe)
+                .index,
+            17
+        );
+        // The elements found to be untestable are not counted as remaining,
+        // so the estimates reach zero even though they are still in the range.
+        for (tested, remaining) in probe.remaining.iter().enumerate() {
+            assert!(tested + 1 + remaining.elements <= results.len() - 2);
+        }
+        assert_eq!(
+            probe.remaining.last(),
+            Some(&Remaining {
+                elements: 0,
+                steps: 0,
+                confidence: None
+            })
+        );
+    }
+
+    #[test]
+    fn bisection_estimates_leave_out_unknown_ranges() {
+        let results: Vec<Satisfies> = (0..33)
+            .map(|i| match i {
+                0..=14 => No,
+                15..=16 => Unknown,
+                _ => Yes,
+            })
+            .collect();
+        let mut probe = Recorder {
+            results: &results,
+            ..Recorder::default()
+        };
+        assert_eq!(Bisection.search(results.len(), &mut probe).index, 17);
+        // 23 is tested between 14 and 32, once 15 and 16 are known to be
+        // unknown.
+        let i = probe.tested.iter().position(|&i| i == 23).unwrap();
+        assert_eq!(probe.remaining[i].elements, (32 - 14 + 1 - 2) / 2);
+        assert_eq!(probe.remaining[i].steps, estimate_steps(32 - 14 + 1 - 2));
+    }
+
+    #[test]
+    fn trials_without_runs_are_unknown() {
+        let mut trials = Trials::default();
+        assert_eq!(trials.satisfies(), Unknown);
+        trials.baseline += 1;
+        assert_eq!(trials.satisfies(), No);
+        trials.regressed += 1;
+        assert_eq!(trials.satisfies(), Yes);
+    }
+
     #[test]
     fn qc_prop() {
         QuickCheck::new().quickcheck(prop as fn(_) -> _);
     }
 
+    #[test]
+    fn qc_prop_parallel() {
+        QuickCheck::new().quickcheck(prop_parallel as fn(_, _) -> _);
+    }
+
     #[test]
     fn estimates() {
         for (n, expect) in &[
@@ -204,7 +838,7 @@ mod tests {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Satisfies {
     Yes,
     No,
@@ -217,6 +851,70 @@ impl fmt::Display for Satisfies {
     }
 }
 
+/// The outcomes of repeatedly testing a single element.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trials {
+    /// Number of runs which satisfied the predicate.
+    pub regressed: u32,
+    /// Number of runs which did not satisfy the predicate.
+    pub baseline: u32,
+}
+
+impl Trials {
+    /// An element satisfies the predicate if any of its trials did, and is
+    /// unknown if none were run.
+    #[must_use]
+    pub fn satisfies(self) -> Satisfies {
+        if self.regressed > 0 {
+            Satisfies::Yes
+        } else if self.baseline > 0 {
+            Satisfies::No
+        } else {
+            Satisfies::Unknown
+        }
+    }
+
+    fn add(&mut self, other: Trials) {
+        self.regressed += other.regressed;
+        self.baseline += other.baseline;
+    }
+
+    /// Log-likelihood of these trials, if each run satisfies the predicate
+    /// with probability `rate`.
+    fn log_likelihood(self, rate: f64) -> f64 {
+        f64::from(self.regressed) * rate.ln() + f64::from(self.baseline) * (1.0 - rate).ln()
+    }
+}
+
+impl fmt::Display for Trials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} runs regressed",
+            self.regressed,
+            self.regressed + self.baseline
+        )
+    }
+}
+
+/// The result of a [`Strategy`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub index: usize,
+    /// Posterior probability that `index` is the first satisfying element,
+    /// which is 1 unless the search is probabilistic.
+    pub confidence: f64,
+}
+
+impl Estimate {
+    fn certain(index: usize) -> Estimate {
+        Estimate {
+            index,
+            confidence: 1.0,
+        }
+    }
+}
+
 impl From<Option<bool>> for Satisfies {
     fn from(o: Option<bool>) -> Self {
         match o {
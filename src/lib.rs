@@ -0,0 +1,2994 @@
+//! Bisects rustc toolchains to find the nightly, commit or release which
+//! introduced a regression, as `cargo bisect-rustc` does.
+//!
+//! A bisection is set up with a [`ConfigBuilder`] from [`Config::builder`], or
+//! from the arguments of `cargo bisect-rustc` with
+//! [`Config::from_command_line`], and reports its progress to a [`Reporter`]:
+//!
+//! ```no_run
+//! use cargo_bisect_rustc::{Bound, Config, Progress, Reporter};
+//!
+//! let reporter = Reporter::new(|progress| {
+//!     if let Progress::Warning(warning) = progress {
+//!         eprintln!("warning: {warning}");
+//!     }
+//! });
+//! let cfg = Config::builder()
+//!     .start("2022-08-01".parse::<Bound>()?)
+//!     .end("2022-08-10".parse::<Bound>()?)
+//!     .build(reporter)?;
+//! let result = cfg.bisect_nightlies()?;
+//! println!("regressed in {}", result.searched[result.found]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+#![warn(clippy::pedantic)]
+#![warn(clippy::cargo)]
+#![allow(clippy::semicolon_if_nothing_returned)]
+#![allow(clippy::let_underscore_drop)]
+#![allow(clippy::single_match_else)]
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Date, Duration, NaiveDate, Utc};
+use clap::{ArgEnum, Parser, PossibleValue};
+use colored::Colorize;
+use anyhow::{bail, Context};
+use log::debug;
+use regex::Regex;
+use reqwest::blocking::Client;
+
+// First, so that its macros can be used in the other modules.
+#[macro_use]
+mod progress;
+
+mod builder;
+mod cache;
+mod command;
+mod container;
+mod git;
+mod github;
+mod least_satisfying;
+mod manifest;
+mod mirrors;
+mod offline;
+mod perf;
+mod predicate;
+mod prefetch;
+mod releases;
+mod repo_access;
+mod report;
+mod sandbox;
+mod session;
+mod skip;
+mod toolchains;
+
+pub use crate::builder::ConfigBuilder;
+use crate::cache::Cache;
+use crate::container::{Container, Engine};
+pub use crate::least_satisfying::{
+    Bisection, Estimate, KArySearch, LinearScan, ProbabilisticSearch, Probe, Remaining, Satisfies,
+    Strategy, Trials, UnknownAwareBisection,
+};
+use crate::mirrors::Mirrors;
+use crate::perf::{Comparison, Metric, StatTest};
+use crate::predicate::Expr;
+pub use crate::predicate::{And, EmitsLint, Not, Or, OutputMatches, Predicate, TestInput, Verdict};
+use crate::prefetch::Prefetcher;
+pub use crate::progress::{Progress, Reporter};
+use crate::releases::{releases_between, Version};
+use crate::report::{Phases, Step};
+pub use crate::repo_access::{
+    AccessViaCommitsFile, AccessViaGithub, AccessViaLocalGit, RustRepositoryAccessor,
+};
+use crate::sandbox::{Limits, Sandbox};
+use crate::session::Journal;
+use crate::skip::Skip;
+pub use crate::toolchains::{
+    ArchiveError, DownloadError, DownloadParams, InstallError, TestOutcome, TestRun, Toolchain,
+    ToolchainSpec,
+};
+use crate::toolchains::{download_progress, parse_to_utc_date, DOWNLOAD_ATTEMPTS, YYYY_MM_DD};
+
+/// A commit of the rust-lang/rust repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub sha: String,
+    pub date: GitDate,
+    pub summary: String,
+}
+
+/// The first commit which build artifacts are made available through the CI for
+/// bisection.
+///
+/// Due to our deletion policy which expires builds after 167 days, the build
+/// artifacts of this commit itself is no longer available, so this may not be entirely useful;
+/// however, it does limit the amount of commits somewhat.
+const EPOCH_COMMIT: &str = "927c55d86b0be44337f37cf5b0a76fb8ba86e06c";
+
+/// Exit code with which a test signals that the toolchain cannot be judged,
+/// as with `git bisect run`.
+const SKIP_EXIT_CODE: i32 = 125;
+
+const REPORT_HEADER: &str = "\
+==================================================================================
+= Please file this regression report on the rust-lang/rust GitHub repository     =
+=        New issue: https://github.com/rust-lang/rust/issues/new                 =
+=     Known issues: https://github.com/rust-lang/rust/issues                     =
+= Copy and paste the text below into the issue report thread.  Thanks!           =
+==================================================================================";
+
+#[derive(Debug, Parser)]
+#[clap(bin_name = "cargo", subcommand_required = true)]
+enum Cargo {
+    BisectRustc(Opts),
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    bin_name = "cargo bisect-rustc",
+    version,
+    about,
+    after_help = "EXAMPLES:
+    Run a fully automatic nightly bisect doing `cargo check`:
+    ```
+    cargo bisect-rustc --start 2018-07-07 --end 2018-07-30 --test-dir ../my_project/ -- check
+    ```
+
+    Run a PR-based bisect with manual prompts after each run doing `cargo build`:
+    ```
+    cargo bisect-rustc --start 6a1c0637ce44aeea6c60527f4c0e7fb33f2bcd0d \\
+      --end 866a713258915e6cbb212d135f751a6a8c9e1c0a --test-dir ../my_project/ --prompt -- build
+    ```"
+)]
+#[allow(clippy::struct_excessive_bools)]
+struct Opts {
+    #[clap(
+        long,
+        help = "Custom regression definition",
+        arg_enum,
+        default_value_t = RegressOn::ErrorStatus,
+    )]
+    regress: RegressOn,
+
+    #[clap(
+        long,
+        value_name = "EXPR",
+        conflicts_with = "regress",
+        help = "Custom regression definition combining --regress values with `and`, `or`, \
+`not` and parentheses, e.g. 'ice or (error and not non-error)'"
+    )]
+    regress_if: Option<Expr>,
+
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only regress if stderr matches the given regex (in addition to --regress)"
+    )]
+    regress_if_stderr_matches: Option<Regex>,
+
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only regress if stdout matches the given regex (in addition to --regress)"
+    )]
+    regress_if_stdout_matches: Option<Regex>,
+
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only regress if stderr does not match the given regex (in addition to --regress)"
+    )]
+    regress_unless_stderr_matches: Option<Regex>,
+
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only regress if stdout does not match the given regex (in addition to --regress)"
+    )]
+    regress_unless_stdout_matches: Option<Regex>,
+
+    #[clap(short, long, help = "Download the alt build instead of normal build")]
+    alt: bool,
+
+    #[clap(
+        long,
+        help = "Host triple for the compiler",
+        default_value_t = option_env!("HOST").unwrap_or("unkown").to_string(),
+        validator = validate_host
+        )]
+    host: String,
+
+    #[clap(long, help = "Cross-compilation target platform")]
+    target: Option<String>,
+
+    #[clap(long, help = "Preserve the downloaded artifacts")]
+    preserve: bool,
+
+    #[clap(long, help = "Preserve the target directory used for builds")]
+    preserve_target: bool,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Directory to cache downloaded tarballs in [default: RUSTUP_HOME/bisector-cache]",
+        parse(from_os_str)
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "MB",
+        help = "Maximum size of the download cache in megabytes",
+        default_value_t = 8192
+    )]
+    cache_size: u64,
+
+    #[clap(long, help = "Do not cache downloaded tarballs")]
+    no_cache: bool,
+
+    #[clap(
+        long,
+        value_name = "URL",
+        env = "CARGO_BISECT_RUSTC_NIGHTLY_SERVER",
+        use_value_delimiter = true,
+        help = "Base URL of the dist server to download nightlies and releases from, \
+or of mirrors of it to try in order [default: https://static.rust-lang.org/dist]"
+    )]
+    nightly_server: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "URL",
+        env = "CARGO_BISECT_RUSTC_CI_SERVER",
+        use_value_delimiter = true,
+        help = "Base URL of the server to download CI artifacts from, \
+or of mirrors of it to try in order \
+[default: https://s3-us-west-1.amazonaws.com/rust-lang-ci2]"
+    )]
+    ci_server: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Number of attempts at each download, retrying on transient errors",
+        default_value_t = DOWNLOAD_ATTEMPTS,
+        validator = validate_download_attempts
+    )]
+    download_attempts: u32,
+
+    #[clap(
+        long,
+        help = "Do not download the toolchains which may be tested next while testing one"
+    )]
+    no_prefetch: bool,
+
+    #[clap(long, help = "Download rust-src [default: no download]")]
+    with_src: bool,
+
+    #[clap(long, help = "Download rustc-dev [default: no download]")]
+    with_dev: bool,
+
+    #[clap(short, long = "component", help = "additional components to install")]
+    components: Vec<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "Bisect a tool rather than rustc, installing its components and running it \
+instead of `cargo build`"
+    )]
+    tool: Option<Tool>,
+
+    #[clap(
+        long,
+        value_name = "LINT",
+        help = "With --tool=clippy, regress when the given lint is emitted \
+(or when it is not emitted, with --regress=success)"
+    )]
+    lint: Option<String>,
+
+    #[clap(
+        long,
+        help = "Root directory for tests",
+        default_value = ".",
+        parse(from_os_str),
+        validator = validate_dir
+    )]
+    test_dir: PathBuf,
+
+    #[clap(long, help = "Manually evaluate for regression with prompts")]
+    prompt: bool,
+
+    #[clap(
+        long,
+        short,
+        help = "Kill the test after specified number of seconds (for bisecting hangs)"
+    )]
+    timeout: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Run the test in a sandbox with resource limits, a private temporary directory \
+and, where supported, no network access and a read-only filesystem outside of the test directory and CARGO_HOME"
+    )]
+    sandbox: bool,
+
+    #[clap(
+        long,
+        value_name = "SECS",
+        requires = "sandbox",
+        help = "Limit the CPU time of each process of the sandboxed test"
+    )]
+    sandbox_cpu: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "MB",
+        requires = "sandbox",
+        help = "Limit the address space of each process of the sandboxed test"
+    )]
+    sandbox_memory: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "MB",
+        requires = "sandbox",
+        help = "Limit the size of the files written by the sandboxed test"
+    )]
+    sandbox_file_size: Option<u64>,
+
+    #[clap(
+        long,
+        requires = "sandbox",
+        help = "Let the sandboxed test access the network"
+    )]
+    sandbox_network: bool,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        requires = "sandbox",
+        help = "Let the sandboxed test write to the given directory",
+        parse(from_os_str)
+    )]
+    sandbox_writable: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "IMAGE",
+        help = "Run the test in a container of the given Docker or Podman image, \
+with the toolchain and the test directory mounted into it"
+    )]
+    container: Option<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "ENGINE",
+        requires = "container",
+        help = "Container engine to run --container with [default: docker if installed, \
+else podman]"
+    )]
+    container_engine: Option<Engine>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "POLICY",
+        help = "How to treat a test which runs for longer than --timeout",
+        default_value_t = TimeoutPolicy::Regressed
+    )]
+    timeout_policy: TimeoutPolicy,
+
+    #[clap(
+        long,
+        short,
+        value_name = "N",
+        help = "Install and test up to this many toolchains in parallel, splitting the range \
+in more than two at each step",
+        default_value_t = 1,
+        validator = validate_jobs
+    )]
+    jobs: usize,
+
+    #[clap(
+        long,
+        help = "Run the test this many times on each toolchain, and bisect probabilistically \
+(for regressions which do not reproduce every time)",
+        default_value_t = 1,
+        validator = validate_trials
+    )]
+    trials: u32,
+
+    #[clap(
+        long,
+        help = "Confidence required in the result of a probabilistic bisection",
+        default_value_t = 0.95,
+        validator = validate_confidence
+    )]
+    confidence: f64,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "How to search the range for the regression [default: bisect, or k-ary with \
+--jobs and probabilistic with --trials]"
+    )]
+    strategy: Option<SearchStrategy>,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "What to measure with --regress=slower-than",
+        default_value_t = Metric::WallTime
+    )]
+    perf_metric: Metric,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Number of measurements on each toolchain with --regress=slower-than",
+        default_value_t = 5,
+        validator = validate_perf_runs
+    )]
+    perf_runs: u32,
+
+    #[clap(
+        long,
+        value_name = "PERCENT",
+        help = "How much slower than the start of the range the median measurement must be \
+to count as a regression with --regress=slower-than",
+        default_value_t = 5.0
+    )]
+    perf_threshold: f64,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "Statistical test which a slowdown must pass to count as a regression \
+with --regress=slower-than",
+        default_value_t = StatTest::MannWhitney
+    )]
+    perf_test: StatTest,
+
+    #[clap(
+        long,
+        value_name = "ALPHA",
+        help = "Significance level of --perf-test",
+        default_value_t = 0.05,
+        validator = validate_significance
+    )]
+    perf_significance: f64,
+
+    #[clap(
+        long,
+        value_name = "MB",
+        help = "With --regress=peak-memory, regress when the test uses more memory than this"
+    )]
+    max_memory: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "RATIO",
+        help = "With --regress=peak-memory, regress when the test uses more than this many \
+times the memory it used with the start of the range"
+    )]
+    max_memory_ratio: Option<f64>,
+
+    #[clap(short, long = "verbose", parse(from_occurrences))]
+    verbosity: usize,
+
+    #[clap(
+        help = "Arguments to pass to cargo or the file specified by --script during tests",
+        multiple_values = true,
+        last = true,
+        parse(from_os_str)
+    )]
+    command_args: Vec<OsString>,
+
+    #[clap(
+        long,
+        help = "Left bound for search (*without* regression). You can use \
+a date (YYYY-MM-DD), git tag name (e.g. 1.58.0) or git commit SHA."
+    )]
+    start: Option<Bound>,
+
+    #[clap(
+        long,
+        help = "Right bound for search (*with* regression). You can use \
+a date (YYYY-MM-DD), git tag name (e.g. 1.58.0) or git commit SHA."
+    )]
+    end: Option<Bound>,
+
+    #[clap(long, help = "Bisect via commit artifacts")]
+    by_commit: bool,
+
+    #[clap(
+        long,
+        help = "Bisect stable releases between --start and --end (e.g. 1.60.0 and 1.64.0), \
+then nightlies between the branch points of the releases around the regression"
+    )]
+    by_release: bool,
+
+    #[clap(long, arg_enum, help = "How to access Rust git repository", default_value_t = Access::Checkout)]
+    access: Access,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "File listing the commits of the range, written by --prefetch-to, \
+for --access=offline",
+        parse(from_os_str),
+        required_if_eq("access", "offline")
+    )]
+    commits_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Download the toolchains and commits between --start and --end into the given \
+directory instead of bisecting, for a bisection without network access",
+        parse(from_os_str)
+    )]
+    prefetch_to: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "DATE|SHA|RANGE",
+        help = "Leave the nightly or commit out of the bisection, e.g. because it is broken for \
+unrelated reasons, or the inclusive range of them written as START..END"
+    )]
+    skip: Vec<Skip>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Leave the nightlies and commits listed in the given file out of the bisection, \
+one --skip value per line",
+        parse(from_os_str),
+        validator = validate_file
+    )]
+    skip_file: Option<PathBuf>,
+
+    #[clap(long, help = "Install the given artifact")]
+    install: Option<Bound>,
+
+    #[clap(long, help = "Force installation over existing artifacts")]
+    force_install: bool,
+
+    #[clap(
+        long,
+        help = "Script replacement for `cargo build` command, which may exit with 125 to skip \
+the toolchain",
+        parse(from_os_str),
+        validator = validate_file,
+    )]
+    script: Option<PathBuf>,
+
+    #[clap(long, help = "Do not install cargo [default: install cargo]")]
+    without_cargo: bool,
+
+    #[clap(
+        long,
+        help = "Resume the bisection recorded in the given session journal",
+        parse(from_os_str)
+    )]
+    resume: Option<PathBuf>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "FORMAT",
+        help = "Format of the bisection report",
+        default_value_t = ReportFormat::Human
+    )]
+    report_format: ReportFormat,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Write a JSON bisection report to the given file [default: stdout]",
+        parse(from_os_str)
+    )]
+    report_file: Option<PathBuf>,
+}
+
+/// The date of a commit or nightly.
+pub type GitDate = Date<Utc>;
+
+fn validate_dir(s: &str) -> anyhow::Result<()> {
+    let path: PathBuf = s.parse()?;
+    if path.is_dir() {
+        Ok(())
+    } else {
+        bail!(
+            "{} is not an existing directory",
+            path.canonicalize()?.display()
+        )
+    }
+}
+
+fn validate_file(s: &str) -> anyhow::Result<()> {
+    let path: PathBuf = s.parse()?;
+    if path.is_file() {
+        Ok(())
+    } else {
+        bail!("{} is not an existing file", path.canonicalize()?.display())
+    }
+}
+
+fn validate_trials(s: &str) -> anyhow::Result<()> {
+    if s.parse::<u32>()? == 0 {
+        bail!("the number of trials must be at least 1")
+    }
+    Ok(())
+}
+
+fn validate_download_attempts(s: &str) -> anyhow::Result<()> {
+    if s.parse::<u32>()? == 0 {
+        bail!("the number of download attempts must be at least 1")
+    }
+    Ok(())
+}
+
+fn validate_jobs(s: &str) -> anyhow::Result<()> {
+    if s.parse::<usize>()? == 0 {
+        bail!("the number of jobs must be at least 1")
+    }
+    Ok(())
+}
+
+fn validate_confidence(s: &str) -> anyhow::Result<()> {
+    let confidence: f64 = s.parse()?;
+    if 0.0 < confidence && confidence < 1.0 {
+        Ok(())
+    } else {
+        bail!("the confidence must be between 0 and 1 (exclusive)")
+    }
+}
+
+fn validate_perf_runs(s: &str) -> anyhow::Result<()> {
+    if s.parse::<u32>()? < 2 {
+        bail!("the number of measurements must be at least 2")
+    }
+    Ok(())
+}
+
+fn validate_significance(s: &str) -> anyhow::Result<()> {
+    let alpha: f64 = s.parse()?;
+    if 0.0 < alpha && alpha < 1.0 {
+        Ok(())
+    } else {
+        bail!("the significance level must be between 0 and 1 (exclusive)")
+    }
+}
+
+fn validate_host(s: &str) -> anyhow::Result<()> {
+    if s == "unknown" {
+        bail!(
+            "Failed to auto-detect host triple and was not specified. Please provide it via --host"
+        )
+    } else {
+        Ok(())
+    }
+}
+
+/// One end of the range to bisect, as given with `--start` and `--end`.
+#[derive(Clone, Debug)]
+pub enum Bound {
+    /// A commit, or a release such as `1.62.0` with `--by-release`.
+    Commit(String),
+    /// The nightly of a day.
+    Date(GitDate),
+}
+
+impl FromStr for Bound {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_to_utc_date(s)
+            .map(Self::Date)
+            .or_else(|_| Ok(Self::Commit(s.to_string())))
+    }
+}
+
+impl Bound {
+    /// Looks up the commit of a nightly on the dist `servers`.
+    fn sha(&self, servers: &[String], reporter: &Reporter) -> anyhow::Result<String> {
+        match self {
+            Bound::Commit(commit) => Ok(commit.clone()),
+            Bound::Date(date) => {
+                let date_str = date.format(YYYY_MM_DD);
+                let urls = mirrors::urls(
+                    servers,
+                    &format!("/{date_str}/channel-rust-nightly-git-commit-hash.txt"),
+                );
+
+                let client = Client::new();
+                let name = format!("nightly manifest {date_str}");
+                let commit = mirrors::first_available(&urls, reporter, |url| {
+                    status!(reporter, "fetching {url}");
+                    let mut response = download_progress(&client, &name, url, reporter)?;
+                    let mut commit = String::new();
+                    response
+                        .read_to_string(&mut commit)
+                        .map_err(DownloadError::Io)?;
+                    Ok(commit)
+                })?;
+
+                status!(reporter, "converted {date_str} to {commit}");
+
+                Ok(commit)
+            }
+        }
+    }
+
+    fn as_commit(&self, servers: &[String], reporter: &Reporter) -> anyhow::Result<Self> {
+        self.sha(servers, reporter).map(Bound::Commit)
+    }
+}
+
+impl Opts {
+    fn emit_cargo_output(&self) -> bool {
+        self.verbosity >= 2
+    }
+
+    /// Whether the output of the test is shown.
+    fn emit_output(&self) -> bool {
+        self.emit_cargo_output() || self.prompt
+    }
+
+    /// Arguments to cargo for testing, if none were given.
+    fn default_cargo_args(&self) -> &'static [&'static str] {
+        self.tool.map_or(&["build"], Tool::cargo_args)
+    }
+
+    /// The predicate deciding whether a test regressed: `--regress` or
+    /// `--regress-if`, refined by `--lint` and the `--regress-*-matches`
+    /// patterns, which must all agree.
+    fn predicate(&self) -> Box<dyn Predicate> {
+        let mut predicate: Box<dyn Predicate> = match (&self.regress_if, &self.lint) {
+            (Some(expr), _) => expr.to_predicate(),
+            // `--regress=error` looks for a lint appearing, and
+            // `--regress=success` for a lint disappearing.
+            (None, Some(lint)) if self.regress == RegressOn::ErrorStatus => {
+                Box::new(EmitsLint(lint.clone()))
+            }
+            (None, Some(lint)) => Box::new(EmitsLint(lint.clone()).not()),
+            (None, None) => Box::new(self.regress),
+        };
+        if let Some(ref regex) = self.regress_if_stderr_matches {
+            predicate = Box::new(predicate.and(OutputMatches::Stderr(regex.clone())));
+        }
+        if let Some(ref regex) = self.regress_if_stdout_matches {
+            predicate = Box::new(predicate.and(OutputMatches::Stdout(regex.clone())));
+        }
+        if let Some(ref regex) = self.regress_unless_stderr_matches {
+            predicate = Box::new(predicate.and(OutputMatches::Stderr(regex.clone()).not()));
+        }
+        if let Some(ref regex) = self.regress_unless_stdout_matches {
+            predicate = Box::new(predicate.and(OutputMatches::Stdout(regex.clone()).not()));
+        }
+        predicate
+    }
+
+    /// Checks that the options refining `--regress` fit together.
+    fn validate_regress(&self) -> anyhow::Result<()> {
+        if self.lint.is_some() {
+            if self.regress_if.is_some() {
+                bail!("--lint cannot be used with --regress-if");
+            }
+            if self.tool != Some(Tool::Clippy) {
+                bail!("--lint requires --tool=clippy");
+            }
+            if !matches!(
+                self.regress,
+                RegressOn::ErrorStatus | RegressOn::SuccessStatus
+            ) {
+                bail!("--lint only supports --regress=error and --regress=success");
+            }
+        }
+
+        if self.regress == RegressOn::SlowerThan {
+            if self.start.is_none() {
+                bail!("--regress=slower-than requires --start, which is measured as the baseline");
+            }
+            if self.prompt {
+                bail!("--regress=slower-than cannot be used with --prompt");
+            }
+            if self.is_probabilistic() {
+                bail!(
+                    "--regress=slower-than cannot be used with --trials, use --perf-runs instead"
+                );
+            }
+            if self.search_strategy() == SearchStrategy::Probabilistic {
+                bail!("--regress=slower-than cannot be used with --strategy=probabilistic");
+            }
+            if self.perf_metric == Metric::Instructions
+                && process::Command::new("perf")
+                    .arg("--version")
+                    .output()
+                    .is_err()
+            {
+                bail!("--perf-metric=instructions requires `perf` to be installed");
+            }
+            if self.perf_metric == Metric::MaxRss && !cfg!(target_os = "linux") {
+                bail!("--perf-metric=max-rss is only supported on Linux");
+            }
+            if let Some(p) = self.perf_test.smallest_p_value(self.perf_runs) {
+                if p >= self.perf_significance {
+                    bail!(
+                        "with --perf-runs={}, --perf-test={} cannot find a slowdown significant \
+at level {} (the smallest possible p-value is {p:.3}), use more runs",
+                        self.perf_runs,
+                        self.perf_test
+                            .to_possible_value()
+                            .expect("no skipped variants")
+                            .get_name(),
+                        self.perf_significance
+                    );
+                }
+            }
+        }
+
+        if self.regress == RegressOn::PeakMemory {
+            if !cfg!(target_os = "linux") {
+                bail!("--regress=peak-memory is only supported on Linux");
+            }
+            if self.max_memory.is_none() && self.max_memory_ratio.is_none() {
+                bail!("--regress=peak-memory requires --max-memory or --max-memory-ratio");
+            }
+            if self.max_memory_ratio.is_some() && self.start.is_none() {
+                bail!("--max-memory-ratio requires --start, which is measured as the baseline");
+            }
+            if self.prompt {
+                bail!("--regress=peak-memory cannot be used with --prompt");
+            }
+        } else if self.max_memory.is_some() || self.max_memory_ratio.is_some() {
+            bail!("--max-memory and --max-memory-ratio require --regress=peak-memory");
+        }
+
+        if self.jobs > 1 {
+            if self.prompt {
+                bail!("--jobs cannot be used with --prompt");
+            }
+            if self.is_probabilistic() {
+                bail!("--jobs cannot be used with --trials");
+            }
+            // Tests running side by side would slow each other down.
+            if self.regress == RegressOn::SlowerThan {
+                bail!("--jobs cannot be used with --regress=slower-than");
+            }
+            if self.search_strategy() != SearchStrategy::KAry {
+                bail!("--jobs requires --strategy=k-ary");
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the download cache, in `rustup_home` unless `--cache-dir` is
+    /// given.
+    fn cache(&self, rustup_home: &Path) -> anyhow::Result<Option<Cache>> {
+        if self.no_cache {
+            return Ok(None);
+        }
+        let dir = self
+            .cache_dir
+            .clone()
+            .unwrap_or_else(|| rustup_home.join("bisector-cache"));
+        let cache = Cache::new(dir, self.cache_size * 1024 * 1024)
+            .context("failed to create download cache")?;
+        Ok(Some(cache))
+    }
+
+    fn sandbox(&self, reporter: &Reporter) -> anyhow::Result<Option<Sandbox>> {
+        if !self.sandbox {
+            return Ok(None);
+        }
+        if !cfg!(unix) {
+            bail!("--sandbox is only supported on Unix");
+        }
+        let limits = Limits {
+            cpu_secs: self.sandbox_cpu,
+            memory: self.sandbox_memory.map(|mb| mb * 1024 * 1024),
+            file_size: self.sandbox_file_size.map(|mb| mb * 1024 * 1024),
+        };
+        // Cargo takes the lock of its package cache, and may update the
+        // registry, even to build a crate without dependencies. The target
+        // directory is within the test directory, see `CARGO_TARGET_DIR` in
+        // `Toolchain::run_test`.
+        let mut writable = self.sandbox_writable.clone();
+        if let Ok(cargo_home) = home::cargo_home() {
+            if cargo_home.is_dir() {
+                writable.push(cargo_home);
+            }
+        }
+        let sandbox = Sandbox::new(limits, self.sandbox_network, &writable, reporter)
+            .context("failed to set up the sandbox")?;
+        Ok(Some(sandbox))
+    }
+
+    fn container(&self) -> anyhow::Result<Option<Container>> {
+        let image = match self.container {
+            Some(ref image) => image.clone(),
+            None => return Ok(None),
+        };
+        // The measurements would be those of the container client.
+        if self.regress == RegressOn::PeakMemory
+            || self.regress == RegressOn::SlowerThan && self.perf_metric != Metric::WallTime
+        {
+            bail!("--container does not support measuring memory or instructions");
+        }
+        if self.sandbox {
+            bail!("--container cannot be used with --sandbox");
+        }
+        Ok(Some(Container::new(self.container_engine, image)?))
+    }
+
+    fn is_probabilistic(&self) -> bool {
+        self.trials > 1
+    }
+
+    /// The strategy given with `--strategy`, or the one implied by `--jobs`
+    /// and `--trials`.
+    fn search_strategy(&self) -> SearchStrategy {
+        match self.strategy {
+            Some(strategy) => strategy,
+            None if self.is_probabilistic() => SearchStrategy::Probabilistic,
+            None if self.jobs > 1 => SearchStrategy::KAry,
+            None => SearchStrategy::Bisect,
+        }
+    }
+
+    fn strategy(&self) -> Box<dyn Strategy> {
+        match self.search_strategy() {
+            SearchStrategy::Bisect => Box::new(Bisection),
+            SearchStrategy::UnknownAware => Box::new(UnknownAwareBisection),
+            SearchStrategy::Linear => Box::new(LinearScan),
+            SearchStrategy::KAry => Box::new(KArySearch { jobs: self.jobs }),
+            SearchStrategy::Probabilistic => Box::new(ProbabilisticSearch {
+                confidence: self.confidence,
+            }),
+        }
+    }
+
+    fn wants_json_report(&self) -> bool {
+        self.report_format == ReportFormat::Json || self.report_file.is_some()
+    }
+}
+
+impl Config {
+    /// Decides the outcome of a run of the test with the predicate.
+    fn outcome_of_output(
+        &self,
+        t: &Toolchain,
+        output: &process::Output,
+        duration: StdDuration,
+    ) -> TestOutcome {
+        let stdout_utf8 = String::from_utf8_lossy(&output.stdout);
+        let stderr_utf8 = String::from_utf8_lossy(&output.stderr);
+
+        debug!(
+            "status: {:?} stdout: {:?} stderr: {:?}",
+            output.status, stdout_utf8, stderr_utf8
+        );
+
+        if output.status.code() == Some(SKIP_EXIT_CODE) {
+            status!(
+                self.reporter,
+                "the test exited with {SKIP_EXIT_CODE}, so {t} cannot be judged"
+            );
+            return TestOutcome::Skip;
+        }
+
+        let verdict = self.predicate.verdict(&TestInput {
+            toolchain: t,
+            status: output.status,
+            stdout: &stdout_utf8,
+            stderr: &stderr_utf8,
+            duration,
+        });
+        debug!("outcome_of_output: verdict: {:?}", verdict);
+        match verdict {
+            Verdict::Regressed => TestOutcome::Regressed,
+            Verdict::Baseline => TestOutcome::Baseline,
+            Verdict::Skip => TestOutcome::Skip,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Debug)]
+enum Access {
+    Checkout,
+    Github,
+    Offline,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SearchStrategy {
+    // Plain bisection.
+    Bisect,
+    // Bisection which leaves out the toolchains that could not be tested.
+    UnknownAware,
+    // Every toolchain from the start, until one has the regression.
+    Linear,
+    // Splitting the range in `--jobs + 1` parts at each step.
+    KAry,
+    // Bisection for regressions which do not reproduce every time, usually
+    // with `--trials`.
+    Probabilistic,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    // Human-readable report on stderr.
+    Human,
+    // JSON report on stdout or in `--report-file`, in addition to the
+    // human-readable one.
+    Json,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TimeoutPolicy {
+    // A hang is the regression being bisected.
+    Regressed,
+    // A hang is unrelated to the regression, and counts as not reproducing it.
+    Baseline,
+    // The toolchain cannot be judged, and is skipped like one which failed to
+    // install.
+    Unknown,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Clippy,
+    Rustfmt,
+    Rustdoc,
+    Miri,
+}
+
+impl Tool {
+    /// Components the tool needs in addition to rustc and cargo.
+    fn components(self) -> &'static [&'static str] {
+        match self {
+            Tool::Clippy => &["clippy"],
+            Tool::Rustfmt => &["rustfmt"],
+            // rustdoc ships with rustc
+            Tool::Rustdoc => &[],
+            Tool::Miri => &["miri", "rust-src"],
+        }
+    }
+
+    fn cargo_args(self) -> &'static [&'static str] {
+        match self {
+            Tool::Clippy => &["clippy"],
+            Tool::Rustfmt => &["fmt", "--check"],
+            Tool::Rustdoc => &["doc"],
+            Tool::Miri => &["miri", "test"],
+        }
+    }
+}
+
+impl Opts {
+    fn repo(&self, reporter: &Reporter) -> Box<dyn RustRepositoryAccessor> {
+        let reporter = reporter.clone();
+        match self.access {
+            Access::Checkout => Box::new(AccessViaLocalGit::new(reporter)),
+            Access::Github => Box::new(AccessViaGithub::new(reporter)),
+            Access::Offline => Box::new(AccessViaCommitsFile::new(
+                self.commits_file
+                    .clone()
+                    .expect("--access=offline requires --commits-file"),
+                reporter,
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// Customize what is treated as regression.
+pub enum RegressOn {
+    /// `ErrorStatus`: Marks test outcome as `Regressed` if and only if
+    /// the `rustc` process reports a non-success status. This corresponds to
+    /// when `rustc` has an internal compiler error (ICE) or when it detects an
+    /// error in the input program.
+    ///
+    /// This covers the most common use case for `cargo-bisect-rustc` and is
+    /// thus the default setting.
+    ///
+    /// You explicitly opt into this seting via `--regress=error`.
+    ErrorStatus,
+
+    /// `SuccessStatus`: Marks test outcome as `Regressed` if and only
+    /// if the `rustc` process reports a success status. This corresponds to
+    /// when `rustc` believes it has successfully compiled the program. This
+    /// covers the use case for when you want to bisect to see when a bug was
+    /// fixed.
+    ///
+    /// You explicitly opt into this seting via `--regress=success`.
+    SuccessStatus,
+
+    /// `IceAlone`: Marks test outcome as `Regressed` if and only if
+    /// the `rustc` process issues a diagnostic indicating that an internal
+    /// compiler error (ICE) occurred. This covers the use case for when you
+    /// want to bisect to see when an ICE was introduced pon a codebase that is
+    /// meant to produce a clean error.
+    ///
+    /// You explicitly opt into this seting via `--regress=ice`.
+    IceAlone,
+
+    /// `NotIce`: Marks test outcome as `Regressed` if and only if
+    /// the `rustc` process does not issue a diagnostic indicating that an
+    /// internal compiler error (ICE) occurred. This covers the use case for
+    /// when you want to bisect to see when an ICE was fixed.
+    ///
+    /// You explicitly opt into this setting via `--regress=non-ice`
+    NotIce,
+
+    /// `NonCleanError`: Marks test outcome as `Baseline` if and only
+    /// if the `rustc` process reports error status and does not issue any
+    /// diagnostic indicating that an internal compiler error (ICE) occurred.
+    /// This is the use case if the regression is a case where an ill-formed
+    /// program has stopped being properly rejected by the compiler.
+    ///
+    /// (The main difference between this case and `SuccessStatus` is
+    /// the handling of ICE: `SuccessStatus` assumes that ICE should be
+    /// considered baseline; `NonCleanError` assumes ICE should be
+    /// considered a sign of a regression.)
+    ///
+    /// You explicitly opt into this seting via `--regress=non-error`.
+    NonCleanError,
+
+    /// `SlowerThan`: Marks test outcome as `Regressed` if and only if the
+    /// test command is significantly slower than with the toolchain at the
+    /// start of the range, as measured over `--perf-runs` runs. This covers
+    /// the use case for when you want to bisect a performance regression,
+    /// such as a compile time regression.
+    ///
+    /// You explicitly opt into this setting via `--regress=slower-than`.
+    SlowerThan,
+
+    /// `PeakMemory`: Marks test outcome as `Regressed` if and only if the
+    /// peak resident memory of the test command exceeds `--max-memory`, or
+    /// `--max-memory-ratio` times its peak with the toolchain at the start of
+    /// the range. This covers the use case for when you want to bisect a
+    /// memory usage regression, such as a compiler running out of memory.
+    ///
+    /// You explicitly opt into this setting via `--regress=peak-memory`.
+    PeakMemory,
+}
+
+impl ArgEnum for RegressOn {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::ErrorStatus,
+            Self::SuccessStatus,
+            Self::IceAlone,
+            Self::NotIce,
+            Self::NonCleanError,
+            Self::SlowerThan,
+            Self::PeakMemory,
+        ]
+    }
+    fn to_possible_value<'a>(&self) -> Option<PossibleValue<'a>> {
+        Some(PossibleValue::new(match self {
+            Self::ErrorStatus => "error",
+            Self::NonCleanError => "non-error",
+            Self::IceAlone => "ice",
+            Self::NotIce => "non-ice",
+            Self::SuccessStatus => "success",
+            Self::SlowerThan => "slower-than",
+            Self::PeakMemory => "peak-memory",
+        }))
+    }
+}
+
+/// A bisection, set up from the command line of `cargo bisect-rustc`.
+pub struct Config {
+    args: Opts,
+    rustup_tmp_path: PathBuf,
+    toolchains_path: PathBuf,
+    target: String,
+    is_commit: bool,
+    client: Client,
+    journal: Option<Mutex<Journal>>,
+    cache: Option<Cache>,
+    sandbox: Option<Sandbox>,
+    container: Option<Container>,
+    mirrors: Mirrors,
+    predicate: Box<dyn Predicate>,
+    /// The nightlies and commits given with `--skip` and `--skip-file`.
+    skips: Vec<Skip>,
+    reporter: Reporter,
+    /// The arguments which the bisection was set up with, to reproduce it.
+    raw_args: Vec<String>,
+    /// Toolchains tested in the current bisection phase, for the JSON report.
+    steps: Mutex<Vec<Step>>,
+    /// Measurements of the start of the current bisection phase, which other
+    /// toolchains are compared to with `--regress=slower-than`.
+    perf_baseline: Mutex<Option<Vec<f64>>>,
+    /// Peak memory usage of the start of the current bisection phase, for
+    /// `--max-memory-ratio`.
+    memory_baseline: Mutex<Option<u64>>,
+}
+
+impl Config {
+    fn from_args(
+        mut args: Opts,
+        raw_args: Vec<String>,
+        journal: Option<Journal>,
+        reporter: Reporter,
+    ) -> anyhow::Result<Config> {
+        let target = args.target.clone().unwrap_or_else(|| args.host.clone());
+
+        let mut toolchains_path = home::rustup_home()?;
+        let sessions_path = toolchains_path.join("bisector-sessions");
+
+        let cache = args.cache(&toolchains_path)?;
+        let sandbox = args.sandbox(&reporter)?;
+        let container = args.container()?;
+        let mirrors = Mirrors::new(
+            &args.nightly_server,
+            &args.ci_server,
+            &home::cargo_home()?.join(mirrors::CONFIG_FILE),
+        )?;
+
+        // We will download and extract the tarballs into this directory before installing.
+        // Using `~/.rustup/tmp` instead of $TMPDIR ensures we could always perform installation by
+        // renaming instead of copying the whole directory.
+        let rustup_tmp_path = toolchains_path.join("tmp");
+        if !rustup_tmp_path.exists() {
+            fs::create_dir(&rustup_tmp_path)?;
+        }
+
+        toolchains_path.push("toolchains");
+        if !toolchains_path.is_dir() {
+            bail!(
+                "`{}` is not a directory. Please install rustup.",
+                toolchains_path.display()
+            );
+        }
+
+        let is_commit = match (args.start.clone(), args.end.clone()) {
+            (Some(Bound::Commit(_)) | None, Some(Bound::Commit(_)))
+            | (Some(Bound::Commit(_)), None) => Some(true),
+
+            (Some(Bound::Date(_)) | None, Some(Bound::Date(_))) | (Some(Bound::Date(_)), None) => {
+                Some(false)
+            }
+
+            (None, None) => None,
+
+            (start, end) => bail!(
+                "cannot take different types of bounds for start/end, got start: {:?} and end {:?}",
+                start,
+                end
+            ),
+        };
+
+        args.validate_regress()?;
+
+        if args.by_release && args.by_commit {
+            bail!("cannot bisect with both --by-release and --by-commit");
+        }
+
+        if args.prefetch_to.is_some() {
+            if args.by_release {
+                bail!("--prefetch-to cannot be used with --by-release");
+            }
+            if args.start.is_none() {
+                bail!("--prefetch-to requires --start");
+            }
+        }
+
+        if is_commit == Some(false) && args.by_commit {
+            status!(
+                reporter,
+                "finding commit range that corresponds to dates specified"
+            );
+            match (args.start, args.end) {
+                (Some(b1), Some(b2)) => {
+                    args.start = Some(b1.as_commit(mirrors.nightly(), &reporter)?);
+                    args.end = Some(b2.as_commit(mirrors.nightly(), &reporter)?);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let mut skips = args.skip.clone();
+        if let Some(ref file) = args.skip_file {
+            skips.extend(skip::read_file(file)?);
+        }
+
+        // Installing a single artifact or prefetching does not need a journal,
+        // since there is nothing to resume, and neither does a configuration
+        // which was not set up from a command line.
+        let journal = match journal {
+            Some(journal) => Some(journal),
+            None if args.install.is_none()
+                && args.prefetch_to.is_none()
+                && !raw_args.is_empty() =>
+            {
+                let journal = Journal::create(&sessions_path, raw_args.clone(), &reporter)?;
+                status!(
+                    reporter,
+                    "recording session to {0}, resume with `--resume {0}`",
+                    journal.path().display()
+                );
+                Some(journal)
+            }
+            None => None,
+        };
+
+        Ok(Config {
+            // Releases look like tags, but are bisected as stable toolchains.
+            is_commit: !args.by_release && (args.by_commit || is_commit == Some(true)),
+            predicate: args.predicate(),
+            skips,
+            args,
+            target,
+            toolchains_path,
+            rustup_tmp_path,
+            client: Client::new(),
+            journal: journal.map(Mutex::new),
+            cache,
+            sandbox,
+            container,
+            mirrors,
+            reporter,
+            raw_args,
+            steps: Mutex::new(Vec::new()),
+            perf_baseline: Mutex::new(None),
+            memory_baseline: Mutex::new(None),
+        })
+    }
+}
+
+/// Translates a tag-like bound (such as `1.62.0`) to a `Bound::Date` so that
+/// bisecting works for versions older than 167 days.
+fn fixup_bounds(
+    repo: &dyn RustRepositoryAccessor,
+    reporter: &Reporter,
+    start: &mut Option<Bound>,
+    end: &mut Option<Bound>,
+) -> anyhow::Result<()> {
+    let is_tag = |bound: &Option<Bound>| -> bool {
+        match bound {
+            Some(Bound::Commit(commit)) => commit.contains('.'),
+            None | Some(Bound::Date(_)) => false,
+        }
+    };
+    let is_datelike = |bound: &Option<Bound>| -> bool {
+        matches!(bound, None | Some(Bound::Date(_))) || is_tag(bound)
+    };
+    if !(is_datelike(start) && is_datelike(end)) {
+        // If the user specified an actual commit for one bound, then don't
+        // even try to convert the other bound to a date.
+        return Ok(());
+    }
+    let fixup = |which: &str, bound: &mut Option<Bound>| -> anyhow::Result<()> {
+        if is_tag(bound) {
+            if let Some(Bound::Commit(tag)) = bound {
+                let date = repo.bound_to_date(Bound::Commit(tag.clone()))?;
+                status!(
+                    reporter,
+                    "translating --{which}={tag} to {date}",
+                    date = date.format(YYYY_MM_DD)
+                );
+                *bound = Some(Bound::Date(date));
+            }
+        }
+        Ok(())
+    };
+    fixup("start", start)?;
+    fixup("end", end)?;
+    Ok(())
+}
+
+fn check_bounds(start: &Option<Bound>, end: &Option<Bound>) -> anyhow::Result<()> {
+    // current UTC date
+    let current = Utc::today();
+    match start.as_ref().zip(end.as_ref()) {
+        // start date is after end date
+        Some((Bound::Date(start), Bound::Date(end))) if end < start => {
+            bail!(
+                "end should be after start, got start: {} and end {}",
+                start,
+                end
+            );
+        }
+        // start date is after current date
+        Some((Bound::Date(start), _)) if start > &current => {
+            bail!(
+                "start date should be on or before current date, got start date request: {} and current date is {}",
+                start,
+                current
+            );
+        }
+        // end date is after current date
+        Some((_, Bound::Date(end))) if end > &current => {
+            bail!(
+                "end date should be on or before current date, got start date request: {} and current date is {}",
+                end,
+                current
+            );
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parses the arguments of either `cargo bisect-rustc` or the
+/// `cargo-bisect-rustc` binary invoked directly.
+fn parse_args(raw_args: &[String]) -> Result<Opts, clap::Error> {
+    match Cargo::try_parse_from(raw_args) {
+        Ok(Cargo::BisectRustc(args)) => Ok(args),
+        Err(e) => {
+            if e.context().next().is_some() {
+                Opts::try_parse_from(raw_args)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Sets up a bisection from the arguments of `cargo bisect-rustc`,
+    /// starting with the program name, and resumes the session journal given
+    /// with `--resume` if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails with a [`clap::Error`] if the arguments are invalid or ask for
+    /// `--help`, and otherwise if the bounds of the range cannot be resolved
+    /// or the toolchains cannot be stored.
+    pub fn from_command_line(
+        mut raw_args: Vec<String>,
+        reporter: Reporter,
+    ) -> anyhow::Result<Config> {
+        let mut args = parse_args(&raw_args)?;
+        let journal = match args.resume {
+            Some(ref path) => {
+                let (journal, session_args) = Journal::resume(path, &reporter)?;
+                raw_args = session_args;
+                args = parse_args(&raw_args)?;
+                Some(journal)
+            }
+            None => None,
+        };
+        Config::from_opts(args, raw_args, journal, reporter)
+    }
+
+    /// Resolves the bounds of the range given in `args`, then sets up the
+    /// bisection.
+    fn from_opts(
+        mut args: Opts,
+        raw_args: Vec<String>,
+        journal: Option<Journal>,
+        reporter: Reporter,
+    ) -> anyhow::Result<Config> {
+        if !args.by_release {
+            let repo = args.repo(&reporter);
+            fixup_bounds(&*repo, &reporter, &mut args.start, &mut args.end)?;
+        }
+        check_bounds(&args.start, &args.end)?;
+        Config::from_args(args, raw_args, journal, reporter)
+    }
+
+    /// Decides whether a test regressed with `predicate`, instead of
+    /// `--regress` and the options refining it. With `--regress=slower-than`
+    /// and `--regress=peak-memory`, it only decides for tests which fail.
+    #[must_use]
+    pub fn with_predicate(mut self, predicate: impl Predicate + 'static) -> Config {
+        self.predicate = Box::new(predicate);
+        self
+    }
+
+    /// Does what the command line asks for: installs a single toolchain with
+    /// `--install`, downloads a range with `--prefetch-to`, or bisects the
+    /// range and reports the regression.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the range is not valid, e.g. if its start already reproduces
+    /// the regression, or if toolchains cannot be downloaded or tested.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        if let Some(ref bound) = self.args.install {
+            self.install(bound)
+        } else if let Some(ref dir) = self.args.prefetch_to {
+            offline::prefetch(self, dir)
+        } else {
+            self.bisect()?;
+            // There is nothing left to resume.
+            if let Some(Ok(journal)) = self.journal.take().map(Mutex::into_inner) {
+                journal.remove();
+            }
+            Ok(())
+        }
+    }
+
+    fn install(&self, bound: &Bound) -> anyhow::Result<()> {
+        match *bound {
+            Bound::Commit(ref release) if release_spec(release).is_some() => {
+                let mut t = Toolchain {
+                    spec: release_spec(release).unwrap(),
+                    host: self.args.host.clone(),
+                    std_targets: vec![self.args.host.clone(), self.target.clone()],
+                };
+                t.std_targets.sort();
+                t.std_targets.dedup();
+                let dl_params = DownloadParams::for_nightly(self);
+                t.install(&self.client, &dl_params)?;
+            }
+            Bound::Commit(ref sha) => {
+                let sha = self.args.repo(&self.reporter).commit(sha)?.sha;
+                let mut t = Toolchain {
+                    spec: ToolchainSpec::Ci {
+                        commit: sha,
+                        alt: self.args.alt,
+                    },
+                    host: self.args.host.clone(),
+                    std_targets: vec![self.args.host.clone(), self.target.clone()],
+                };
+                t.std_targets.sort();
+                t.std_targets.dedup();
+                let dl_params = DownloadParams::for_ci(self);
+                t.install(&self.client, &dl_params)?;
+            }
+            Bound::Date(date) => {
+                let mut t = Toolchain {
+                    spec: ToolchainSpec::Nightly { date },
+                    host: self.args.host.clone(),
+                    std_targets: vec![self.args.host.clone(), self.target.clone()],
+                };
+                t.std_targets.sort();
+                t.std_targets.dedup();
+                let dl_params = DownloadParams::for_nightly(self);
+                t.install(&self.client, &dl_params)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // bisection entry point
+    fn bisect(&mut self) -> anyhow::Result<()> {
+        let mut phases = Phases::default();
+        let result = self.bisect_phases(&mut phases);
+        if self.args.wants_json_report() {
+            // A failed bisection is reported too, along with the phases which
+            // completed before it failed.
+            let written = report::write_json(self, &phases, result.as_ref().err());
+            return result.and(written);
+        }
+        result
+    }
+
+    /// Runs the phases of the bisection, adding each of them to `phases` once
+    /// it completes.
+    fn bisect_phases(&mut self, phases: &mut Phases) -> anyhow::Result<()> {
+        if self.args.by_release {
+            let release_bisection_result = self.bisect_releases()?;
+            let release_found = self.print_results(&release_bisection_result);
+            let narrowed = self.narrow_to_branch_points(&release_bisection_result, release_found);
+            phases.release = Some((release_bisection_result, release_found));
+            if !narrowed? {
+                return Ok(());
+            }
+        }
+
+        if self.is_commit {
+            let bisection_result = self.bisect_ci()?;
+            let found = self.print_results(&bisection_result);
+            phases.ci = Some((bisection_result, found));
+        } else {
+            let nightly_bisection_result = self.bisect_nightlies()?;
+            let nightly_found = self.print_results(&nightly_bisection_result);
+            let nightly_regression =
+                nightly_bisection_result.searched[nightly_bisection_result.found].clone();
+            let (nightly_bisection_result, _) = phases
+                .nightly
+                .insert((nightly_bisection_result, nightly_found));
+
+            if let ToolchainSpec::Nightly { date } = nightly_regression.spec {
+                let previous_date = date.pred();
+
+                let working_commit =
+                    Bound::Date(previous_date).sha(self.mirrors.nightly(), &self.reporter)?;
+                let bad_commit = Bound::Date(date).sha(self.mirrors.nightly(), &self.reporter)?;
+                status!(
+                    self.reporter,
+                    "looking for regression commit between {} and {}",
+                    previous_date.format(YYYY_MM_DD),
+                    date.format(YYYY_MM_DD),
+                );
+
+                let ci_bisection_result = self.bisect_ci_via(&working_commit, &bad_commit)?;
+
+                let ci_found = self.print_results(&ci_bisection_result);
+                print_final_report(self, nightly_bisection_result, &ci_bisection_result);
+                phases.ci = Some((ci_bisection_result, ci_found));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn searched_range(
+    cfg: &Config,
+    searched_toolchains: &[Toolchain],
+) -> (ToolchainSpec, ToolchainSpec) {
+    let first_toolchain = searched_toolchains.first().unwrap().spec.clone();
+    let last_toolchain = searched_toolchains.last().unwrap().spec.clone();
+
+    match (&first_toolchain, &last_toolchain) {
+        (ToolchainSpec::Ci { .. }, ToolchainSpec::Ci { .. })
+        | (ToolchainSpec::Stable { .. }, ToolchainSpec::Stable { .. }) => {
+            (first_toolchain, last_toolchain)
+        }
+
+        _ => {
+            let start_toolchain = if let Some(Bound::Date(date)) = cfg.args.start {
+                ToolchainSpec::Nightly { date }
+            } else {
+                first_toolchain
+            };
+
+            (
+                start_toolchain,
+                ToolchainSpec::Nightly {
+                    date: get_end_date(cfg),
+                },
+            )
+        }
+    }
+}
+
+impl Config {
+    /// Prints the result of a bisection phase, and returns whether the
+    /// regression was found within the searched range.
+    fn print_results(&self, bisection_result: &BisectionResult) -> bool {
+        let BisectionResult {
+            searched: toolchains,
+            skipped,
+            dl_spec,
+            found,
+            confidence,
+            ..
+        } = bisection_result;
+
+        let (start, end) = searched_range(self, toolchains);
+
+        status!(
+            self.reporter,
+            "searched toolchains {} through {}",
+            start,
+            end
+        );
+        if !skipped.is_empty() {
+            status!(self.reporter, "skipped by user: {}", list(skipped));
+        }
+
+        // A probabilistic bisection already accounts for the uncertainty of the
+        // last toolchain, and testing it once more would not be conclusive.
+        if toolchains[*found] == *toolchains.last().unwrap() && confidence.is_none() {
+            // FIXME: Ideally the BisectionResult would contain the final result.
+            // This ends up testing a toolchain that was already tested.
+            // I believe this is one of the duplicates mentioned in
+            // https://github.com/rust-lang/cargo-bisect-rustc/issues/85
+            status!(
+                self.reporter,
+                "checking last toolchain to determine final result"
+            );
+            let t = &toolchains[*found];
+            let r = match t.install(&self.client, dl_spec) {
+                Ok(()) => {
+                    let outcome = t.test(self).outcome;
+                    remove_toolchain(self, t, dl_spec);
+                    self.satisfies(outcome)
+                }
+                Err(_) => {
+                    let _ = t.remove(dl_spec);
+                    Satisfies::Unknown
+                }
+            };
+            match r {
+                Satisfies::Yes => {}
+                Satisfies::No | Satisfies::Unknown => {
+                    status!(
+                        self.reporter,
+                        "error: The regression was not found. Expanding the bounds may help."
+                    );
+                    return false;
+                }
+            }
+        }
+
+        let tc_found = match confidence {
+            Some(confidence) => format!(
+                "Regression in {} (confidence: {:.1}%)",
+                toolchains[*found],
+                confidence * 100.0
+            ),
+            None => format!("Regression in {}", toolchains[*found]),
+        };
+        status!(self.reporter);
+        status!(self.reporter);
+        status!(self.reporter, "{}", "*".repeat(80).dimmed().bold());
+        status!(self.reporter, "{}", tc_found.red());
+        status!(self.reporter, "{}", "*".repeat(80).dimmed().bold());
+        status!(self.reporter);
+        true
+    }
+}
+
+/// Lists toolchains on one line, for the report.
+fn list(toolchains: &[Toolchain]) -> String {
+    toolchains
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn remove_toolchain(cfg: &Config, toolchain: &Toolchain, dl_params: &DownloadParams) {
+    if cfg.args.preserve {
+        // If `rustup toolchain link` was used to link to nightly, then even
+        // with --preserve, the toolchain link should be removed, otherwise it
+        // will go stale after 24 hours.
+        let toolchain_dir = cfg.toolchains_path.join(toolchain.rustup_name());
+        match fs::symlink_metadata(&toolchain_dir) {
+            Ok(meta) => {
+                #[cfg(windows)]
+                let is_junction = {
+                    use std::os::windows::fs::MetadataExt;
+                    (meta.file_attributes() & 1024) != 0
+                };
+                #[cfg(not(windows))]
+                let is_junction = false;
+                if !meta.file_type().is_symlink() && !is_junction {
+                    return;
+                }
+                debug!("removing linked toolchain {}", toolchain);
+            }
+            Err(e) => {
+                debug!(
+                    "remove_toolchain: cannot stat toolchain {}: {}",
+                    toolchain, e
+                );
+                return;
+            }
+        }
+    }
+    if let Err(e) = toolchain.remove(dl_params) {
+        debug!(
+            "failed to remove toolchain {} in {}: {}",
+            toolchain,
+            cfg.toolchains_path.display(),
+            e
+        );
+    }
+}
+
+fn print_final_report(
+    cfg: &Config,
+    nightly_bisection_result: &BisectionResult,
+    ci_bisection_result: &BisectionResult,
+) {
+    let BisectionResult {
+        searched: nightly_toolchains,
+        found: nightly_found,
+        confidence: nightly_confidence,
+        ..
+    } = nightly_bisection_result;
+
+    let BisectionResult {
+        searched: ci_toolchains,
+        found: ci_found,
+        confidence: ci_confidence,
+        ..
+    } = ci_bisection_result;
+
+    status!(cfg.reporter, "{}", REPORT_HEADER.dimmed());
+    status!(cfg.reporter);
+
+    let (start, end) = searched_range(cfg, nightly_toolchains);
+
+    status!(
+        cfg.reporter,
+        "searched nightlies: from {} to {}",
+        start,
+        end
+    );
+
+    status!(
+        cfg.reporter,
+        "regressed nightly: {}",
+        nightly_toolchains[*nightly_found],
+    );
+
+    status!(
+        cfg.reporter,
+        "searched commit range: https://github.com/rust-lang/rust/compare/{0}...{1}",
+        ci_toolchains.first().unwrap(),
+        ci_toolchains.last().unwrap(),
+    );
+
+    status!(
+        cfg.reporter,
+        "regressed commit: https://github.com/rust-lang/rust/commit/{}",
+        ci_toolchains[*ci_found],
+    );
+
+    let skipped: Vec<Toolchain> = [nightly_bisection_result, ci_bisection_result]
+        .iter()
+        .flat_map(|result| result.skipped.iter().cloned())
+        .collect();
+    if !skipped.is_empty() {
+        status!(cfg.reporter, "skipped by user: {}", list(&skipped));
+    }
+
+    if let (Some(nightly_confidence), Some(ci_confidence)) = (nightly_confidence, ci_confidence) {
+        status!(
+            cfg.reporter,
+            "confidence: {:.1}% (nightly), {:.1}% (commit), with {} trials per test",
+            nightly_confidence * 100.0,
+            ci_confidence * 100.0,
+            cfg.args.trials
+        );
+    }
+
+    status!(cfg.reporter);
+    status!(cfg.reporter, "<details>");
+    status!(
+        cfg.reporter,
+        "<summary>bisected with <a href='{}'>cargo-bisect-rustc</a> v{}</summary>",
+        env!("CARGO_PKG_REPOSITORY"),
+        env!("CARGO_PKG_VERSION"),
+    );
+    status!(cfg.reporter);
+    status!(cfg.reporter);
+    if let Some(host) = option_env!("HOST") {
+        status!(cfg.reporter, "Host triple: {}", host);
+    }
+
+    status!(cfg.reporter, "Reproduce with:");
+    status!(cfg.reporter, "```bash");
+    let mut command = "cargo bisect-rustc ".to_string();
+    for arg in cfg.raw_args.iter().skip(2) {
+        command.push_str(arg);
+        command.push(' ');
+    }
+    status!(cfg.reporter, "{command}");
+    status!(cfg.reporter, "```");
+    status!(cfg.reporter, "</details>");
+}
+
+struct NightlyFinderIter {
+    start_date: GitDate,
+    current_date: GitDate,
+}
+
+impl NightlyFinderIter {
+    fn new(start_date: GitDate) -> Self {
+        Self {
+            start_date,
+            current_date: start_date,
+        }
+    }
+}
+
+impl Iterator for NightlyFinderIter {
+    type Item = GitDate;
+
+    fn next(&mut self) -> Option<GitDate> {
+        let current_distance = self.start_date - self.current_date;
+
+        let jump_length = if current_distance.num_days() < 7 {
+            // first week jump by two days
+            2
+        } else if current_distance.num_days() < 49 {
+            // from 2nd to 7th week jump weekly
+            7
+        } else {
+            // from 7th week jump by two weeks
+            14
+        };
+
+        self.current_date = self.current_date - Duration::days(jump_length);
+        Some(self.current_date)
+    }
+}
+
+impl Config {
+    fn install_and_test(
+        &self,
+        t: &Toolchain,
+        dl_spec: &DownloadParams,
+    ) -> Result<Satisfies, InstallError> {
+        if self.args.is_probabilistic() {
+            return self
+                .install_and_test_trials(t, dl_spec)
+                .map(Trials::satisfies);
+        }
+        if let Some(r) = self.recorded_verdict(t) {
+            status!(
+                self.reporter,
+                "RESULT: {t}, ===> {r} (recorded in session journal)"
+            );
+            status!(self.reporter);
+            self.steps
+                .lock()
+                .unwrap()
+                .push(Step::from_session(t, r, None));
+            return Ok(r);
+        }
+        let start = Instant::now();
+        match t.install(&self.client, dl_spec) {
+            Ok(()) => {
+                let install_time = start.elapsed();
+                let run = t.test(self);
+                let r = self.satisfies(run.outcome);
+                match run.peak_rss {
+                    Some(peak_rss) if self.args.regress == RegressOn::PeakMemory => status!(
+                        self.reporter,
+                        "RESULT: {}, ===> {} ({})",
+                        t,
+                        r,
+                        self.describe_memory(peak_rss)
+                    ),
+                    _ => status!(self.reporter, "RESULT: {}, ===> {}", t, r),
+                }
+                self.record_verdict(t, r);
+                self.steps
+                    .lock()
+                    .unwrap()
+                    .push(Step::tested(t, r, install_time, &run));
+                remove_toolchain(self, t, dl_spec);
+                status!(self.reporter);
+                Ok(r)
+            }
+            Err(error) => {
+                self.steps.lock().unwrap().push(Step::failed(t, &error));
+                remove_toolchain(self, t, dl_spec);
+                Err(error)
+            }
+        }
+    }
+
+    /// Installs and tests each of `toolchains` on its own thread, for
+    /// `--jobs`.
+    fn install_and_test_in_parallel(
+        &self,
+        toolchains: &[&Toolchain],
+        dl_spec: &DownloadParams,
+    ) -> Vec<Satisfies> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = toolchains
+                .iter()
+                .map(|t| scope.spawn(move || self.install_and_test(t, dl_spec)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| match handle.join() {
+                    Ok(result) => result.unwrap_or(Satisfies::Unknown),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        })
+    }
+
+    /// Tests `t` as many times as requested by `--trials`.
+    fn install_and_test_trials(
+        &self,
+        t: &Toolchain,
+        dl_spec: &DownloadParams,
+    ) -> Result<Trials, InstallError> {
+        if let Some(trials) = self.recorded_trials(t) {
+            status!(
+                self.reporter,
+                "RESULT: {t}, ===> {trials} (recorded in session journal)"
+            );
+            status!(self.reporter);
+            self.steps.lock().unwrap().push(Step::from_session(
+                t,
+                trials.satisfies(),
+                Some(trials),
+            ));
+            return Ok(trials);
+        }
+        let start = Instant::now();
+        if let Err(error) = t.install(&self.client, dl_spec) {
+            self.steps.lock().unwrap().push(Step::failed(t, &error));
+            remove_toolchain(self, t, dl_spec);
+            return Err(error);
+        }
+        let install_time = start.elapsed();
+        let mut trials = Trials::default();
+        let mut test_time = StdDuration::ZERO;
+        for trial in 1..=self.args.trials {
+            status!(self.reporter, "trial {trial} of {}", self.args.trials);
+            let run = t.test(self);
+            test_time += run.duration;
+            match self.satisfies(run.outcome) {
+                Satisfies::No => trials.baseline += 1,
+                Satisfies::Yes => trials.regressed += 1,
+                // Leave the trial out, as if it was never run.
+                Satisfies::Unknown => {}
+            }
+        }
+        status!(self.reporter, "RESULT: {t}, ===> {trials}");
+        self.steps
+            .lock()
+            .unwrap()
+            .push(Step::tested_trials(t, trials, install_time, test_time));
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.lock().unwrap().record_trials(t, trials) {
+                warning!(self.reporter, "{e:#}");
+            }
+        }
+        remove_toolchain(self, t, dl_spec);
+        status!(self.reporter);
+        Ok(trials)
+    }
+
+    fn satisfies(&self, outcome: TestOutcome) -> Satisfies {
+        // we want to fail, so a successful build doesn't satisfy us
+        match outcome {
+            TestOutcome::Baseline => Satisfies::No,
+            TestOutcome::Regressed => Satisfies::Yes,
+            TestOutcome::Skip => Satisfies::Unknown,
+            TestOutcome::TimedOut => match self.args.timeout_policy {
+                TimeoutPolicy::Regressed => Satisfies::Yes,
+                TimeoutPolicy::Baseline => Satisfies::No,
+                TimeoutPolicy::Unknown => Satisfies::Unknown,
+            },
+        }
+    }
+
+    fn recorded_trials(&self, t: &Toolchain) -> Option<Trials> {
+        self.journal.as_ref()?.lock().unwrap().take_trials(t)
+    }
+
+    fn recorded_verdict(&self, t: &Toolchain) -> Option<Satisfies> {
+        // Measurements are not recorded, so the baseline must be measured
+        // again before other toolchains can be compared to it.
+        let needs_baseline = match self.args.regress {
+            RegressOn::SlowerThan => self.perf_baseline.lock().unwrap().is_none(),
+            RegressOn::PeakMemory => {
+                self.args.max_memory_ratio.is_some()
+                    && self.memory_baseline.lock().unwrap().is_none()
+            }
+            _ => false,
+        };
+        if needs_baseline {
+            return None;
+        }
+        self.journal.as_ref()?.lock().unwrap().verdict(t)
+    }
+
+    /// Returns the steps of the current bisection phase, for the JSON report.
+    fn take_steps(&self) -> Vec<Step> {
+        std::mem::take(&mut *self.steps.lock().unwrap())
+    }
+
+    /// Forgets the measurements of the start of the previous bisection phase.
+    fn reset_baselines(&self) {
+        self.perf_baseline.lock().unwrap().take();
+        self.memory_baseline.lock().unwrap().take();
+    }
+
+    /// Compares the peak memory usage of a toolchain to `--max-memory` and
+    /// `--max-memory-ratio`, or makes it the baseline for the latter if it is
+    /// the first one of the bisection phase.
+    #[allow(clippy::cast_precision_loss)]
+    fn memory_outcome(&self, peak_rss: u64) -> TestOutcome {
+        let baseline = *self.memory_baseline.lock().unwrap().get_or_insert(peak_rss);
+        let over_limit = match self.args.max_memory {
+            Some(max_memory) => peak_rss > max_memory * 1024 * 1024,
+            None => false,
+        };
+        let over_ratio = match self.args.max_memory_ratio {
+            Some(ratio) => peak_rss as f64 > ratio * baseline as f64,
+            None => false,
+        };
+        if over_limit || over_ratio {
+            TestOutcome::Regressed
+        } else {
+            TestOutcome::Baseline
+        }
+    }
+
+    /// Describes the peak memory usage of a toolchain for the `RESULT:` line.
+    #[allow(clippy::cast_precision_loss)]
+    fn describe_memory(&self, peak_rss: u64) -> String {
+        let peak = command::format_bytes(peak_rss);
+        match *self.memory_baseline.lock().unwrap() {
+            Some(baseline) => format!(
+                "peak memory {peak}, {:.2}x the start of the range",
+                peak_rss as f64 / baseline as f64
+            ),
+            None => format!("peak memory {peak}"),
+        }
+    }
+
+    /// Compares the measurements of a toolchain to the baseline, or makes
+    /// them the baseline if they are the first ones of the bisection phase.
+    fn perf_outcome(&self, samples: &[f64]) -> TestOutcome {
+        status!(
+            self.reporter,
+            "{}",
+            perf::describe(self.args.perf_metric, samples)
+        );
+        let mut baseline = self.perf_baseline.lock().unwrap();
+        match baseline.as_deref() {
+            Some(baseline) => {
+                let comparison = Comparison::new(
+                    baseline,
+                    samples,
+                    self.args.perf_threshold,
+                    self.args.perf_test,
+                    self.args.perf_significance,
+                );
+                status!(self.reporter, "{comparison}");
+                if comparison.slower {
+                    TestOutcome::Regressed
+                } else {
+                    TestOutcome::Baseline
+                }
+            }
+            None => {
+                status!(self.reporter, "using these measurements as the baseline");
+                *baseline = Some(samples.to_vec());
+                TestOutcome::Baseline
+            }
+        }
+    }
+
+    fn record_verdict(&self, t: &Toolchain, r: Satisfies) {
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.lock().unwrap().record_verdict(t, r) {
+                warning!(self.reporter, "{e:#}");
+            }
+        }
+    }
+
+    fn record_toolchains(&self, toolchains: &[Toolchain]) {
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.lock().unwrap().record_toolchains(toolchains) {
+                warning!(self.reporter, "{e:#}");
+            }
+        }
+    }
+
+    /// Returns the index of the regressed toolchain, along with the confidence
+    /// in that result if the bisection is probabilistic.
+    fn bisect_to_regression(
+        &self,
+        toolchains: &[Toolchain],
+        dl_spec: &DownloadParams,
+    ) -> (usize, Option<f64>) {
+        self.record_toolchains(toolchains);
+        // Installing over existing toolchains would also install the
+        // prefetched ones again.
+        let prefetch = !(self.args.no_prefetch || self.args.force_install);
+        let mut probe = ToolchainProbe {
+            cfg: self,
+            toolchains,
+            dl_spec,
+            prefetcher: prefetch.then(|| Prefetcher::new(&self.client, dl_spec)),
+        };
+        let estimate = self.args.strategy().search(toolchains.len(), &mut probe);
+        if let Some(prefetcher) = probe.prefetcher {
+            for t in prefetcher.finish() {
+                remove_toolchain(self, &t, dl_spec);
+            }
+        }
+        if self.args.search_strategy() != SearchStrategy::Probabilistic {
+            return (estimate.index, None);
+        }
+        if estimate.confidence < self.args.confidence {
+            warning!(
+                self.reporter,
+                "stopping with {:.1}% confidence",
+                estimate.confidence * 100.0
+            );
+        }
+        (estimate.index, Some(estimate.confidence))
+    }
+}
+
+/// Installs and tests the toolchains picked by the search strategy.
+struct ToolchainProbe<'a> {
+    cfg: &'a Config,
+    toolchains: &'a [Toolchain],
+    dl_spec: &'a DownloadParams,
+    prefetcher: Option<Prefetcher>,
+}
+
+impl<'a> ToolchainProbe<'a> {
+    /// Waits for `index` to be prefetched before testing it.
+    fn toolchain(&mut self, index: usize) -> &'a Toolchain {
+        let t: &'a Toolchain = &self.toolchains[index];
+        if let Some(prefetcher) = &mut self.prefetcher {
+            prefetcher.wait(t);
+        }
+        t
+    }
+}
+
+impl Probe for ToolchainProbe<'_> {
+    fn test(&mut self, indices: &[usize], remaining: &Remaining) -> Vec<Satisfies> {
+        let Remaining {
+            elements, steps, ..
+        } = remaining;
+        if let [index] = *indices {
+            status!(
+                self.cfg.reporter,
+                "{elements} versions remaining to test after this (roughly {steps} steps)"
+            );
+            let t = self.toolchain(index);
+            return vec![self
+                .cfg
+                .install_and_test(t, self.dl_spec)
+                .unwrap_or(Satisfies::Unknown)];
+        }
+        status!(
+            self.cfg.reporter,
+            "testing {} versions in parallel, {elements} remaining after them \
+             (roughly {steps} steps)",
+            indices.len()
+        );
+        let batch: Vec<&Toolchain> = indices.iter().map(|&i| &self.toolchains[i]).collect();
+        self.cfg.install_and_test_in_parallel(&batch, self.dl_spec)
+    }
+
+    fn test_trials(&mut self, index: usize, remaining: &Remaining) -> Option<Trials> {
+        status!(
+            self.cfg.reporter,
+            "most likely regression point has {:.1}% confidence so far",
+            remaining.confidence.unwrap_or_default() * 100.0
+        );
+        let t = self.toolchain(index);
+        // Every trial may have been skipped.
+        self.cfg
+            .install_and_test_trials(t, self.dl_spec)
+            .ok()
+            .filter(|trials| trials.satisfies() != Satisfies::Unknown)
+    }
+
+    fn prefetch(&mut self, next: usize, candidates: &[usize]) {
+        if let Some(prefetcher) = &mut self.prefetcher {
+            let candidates: Vec<&Toolchain> =
+                candidates.iter().map(|&i| &self.toolchains[i]).collect();
+            for t in prefetcher.prefetch(&self.toolchains[next], &candidates) {
+                remove_toolchain(self.cfg, &t, self.dl_spec);
+            }
+        }
+    }
+}
+
+fn get_start_date(cfg: &Config) -> Date<Utc> {
+    if let Some(Bound::Date(date)) = cfg.args.start {
+        date
+    } else {
+        get_end_date(cfg)
+    }
+}
+
+fn get_end_date(cfg: &Config) -> Date<Utc> {
+    if let Some(Bound::Date(date)) = cfg.args.end {
+        date
+    } else if let Some(date) = Toolchain::default_nightly() {
+        date
+    } else {
+        Utc::today()
+    }
+}
+
+fn date_is_future(test_date: Date<Utc>) -> bool {
+    test_date > Utc::today()
+}
+
+impl Config {
+    /// Bisects the nightlies between `--start` and `--end`, or searches
+    /// backwards from the latest nightly for one without the regression if
+    /// `--start` is not given.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the range does not show the regression, or if the nightlies
+    /// cannot be downloaded or tested.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the nightlies run out while searching backwards.
+    #[allow(clippy::too_many_lines)]
+    pub fn bisect_nightlies(&self) -> anyhow::Result<BisectionResult> {
+        if self.args.alt {
+            bail!("cannot bisect nightlies with --alt: not supported");
+        }
+
+        let dl_spec = DownloadParams::for_nightly(self);
+        self.reset_baselines();
+
+        // before this date we didn't have -std packages
+        let end_at = Date::from_utc(NaiveDate::from_ymd(2015, 10, 20), Utc);
+        let mut first_success = None;
+
+        let mut nightly_date = get_start_date(self);
+        let mut last_failure = get_end_date(self);
+        let has_start = self.args.start.is_some();
+
+        // validate start and end dates to confirm that they are not future dates
+        // start date validation
+        if has_start && date_is_future(nightly_date) {
+            bail!(
+                "start date must be on or before the current date. received start date request {}",
+                nightly_date
+            )
+        }
+        // end date validation
+        if date_is_future(last_failure) {
+            bail!(
+                "end date must be on or before the current date. received end date request {}",
+                nightly_date
+            )
+        }
+
+        let mut nightly_iter = NightlyFinderIter::new(nightly_date);
+
+        // this loop tests nightly toolchains to:
+        // (1) validate that start date does not have regression (if defined on command line)
+        // (2) identify a nightly date range for the bisection routine
+        //
+        // The tests here must be constrained to dates after 2015-10-20 (`end_at` date)
+        // because -std packages were not available prior
+        while nightly_date > end_at {
+            let mut t = Toolchain {
+                spec: ToolchainSpec::Nightly { date: nightly_date },
+                host: self.args.host.clone(),
+                std_targets: vec![self.args.host.clone(), self.target.clone()],
+            };
+            t.std_targets.sort();
+            t.std_targets.dedup();
+            if t.is_current_nightly() {
+                status!(
+                    self.reporter,
+                    "checking {} from the currently installed default nightly \
+                       toolchain as the last failure",
+                    t
+                );
+            }
+
+            status!(
+                self.reporter,
+                "checking the start range to find a passing nightly"
+            );
+            match self.install_and_test(&t, &dl_spec) {
+                Ok(r) => {
+                    // If Satisfies::No, then the regression was not identified in this nightly.
+                    // Break out of the loop and use this as the start date for the
+                    // bisection range
+                    if r == Satisfies::No {
+                        first_success = Some(nightly_date);
+                        break;
+                    } else if has_start {
+                        // If this date was explicitly defined on the command line &
+                        // has regression, then this is an error in the test definition.
+                        // The user must re-define the start date and try again
+                        bail!(
+                            "the start of the range ({}) must not reproduce the regression",
+                            t
+                        );
+                    }
+                    last_failure = nightly_date;
+                    nightly_date = nightly_iter.next().unwrap();
+                }
+                Err(InstallError::NotFound { .. } | InstallError::Unavailable { .. }) => {
+                    // go back just one day, presumably missing a nightly
+                    nightly_date = nightly_date.pred();
+                    status!(
+                        self.reporter,
+                        "*** unable to install {}. roll back one day and try again...",
+                        t
+                    );
+                    if has_start {
+                        bail!("could not find {}", t);
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        let first_success = first_success.context("could not find a nightly that built")?;
+
+        // confirm that the end of the date range has the regression
+        let mut t_end = Toolchain {
+            spec: ToolchainSpec::Nightly { date: last_failure },
+            host: self.args.host.clone(),
+            std_targets: vec![self.args.host.clone(), self.target.clone()],
+        };
+        t_end.std_targets.sort();
+        t_end.std_targets.dedup();
+
+        status!(
+            self.reporter,
+            "checking the end range to verify it does not pass"
+        );
+        let result_nightly = self.install_and_test(&t_end, &dl_spec)?;
+        // The regression was not identified in this nightly.
+        if result_nightly == Satisfies::No {
+            bail!(
+                "the end of the range ({}) does not reproduce the regression",
+                t_end
+            );
+        }
+
+        let toolchains = toolchains_between(
+            self,
+            ToolchainSpec::Nightly {
+                date: first_success,
+            },
+            ToolchainSpec::Nightly { date: last_failure },
+        );
+        let toolchains = self.without_missing_components(toolchains, &dl_spec);
+        let (toolchains, skipped) = self.without_skipped(toolchains, &mut Vec::new());
+
+        let (found, confidence) = self.bisect_to_regression(&toolchains, &dl_spec);
+
+        Ok(BisectionResult {
+            dl_spec,
+            searched: toolchains,
+            skipped,
+            found,
+            confidence,
+            steps: self.take_steps(),
+            commits: Vec::new(),
+        })
+    }
+}
+
+impl Config {
+    /// Excludes the toolchains which lack some of the requested components
+    /// from the bisection, instead of failing to install them later on.
+    ///
+    /// The first and last toolchains are kept, since they were already
+    /// installed to check the bounds of the range.
+    fn without_missing_components(
+        &self,
+        toolchains: Vec<Toolchain>,
+        dl_spec: &DownloadParams,
+    ) -> Vec<Toolchain> {
+        if !dl_spec.has_optional_components() {
+            return toolchains;
+        }
+        status!(
+            self.reporter,
+            "checking which toolchains provide the requested components"
+        );
+        let last = toolchains.len() - 1;
+        toolchains
+            .into_iter()
+            .enumerate()
+            .filter(|(i, t)| {
+                if *i == 0 || *i == last {
+                    return true;
+                }
+                match t.missing_components(&self.client, dl_spec) {
+                    Ok(missing) if !missing.is_empty() => {
+                        status!(
+                            self.reporter,
+                            "skipped {t}: component missing: {}",
+                            missing.join(", ")
+                        );
+                        self.steps
+                            .lock()
+                            .unwrap()
+                            .push(Step::skipped_missing(t, &missing));
+                        false
+                    }
+                    Ok(_) => true,
+                    // Installing the toolchain will tell whether it is usable.
+                    Err(e) => {
+                        debug!("could not check the components of {t}: {e}");
+                        true
+                    }
+                }
+            })
+            .map(|(_, t)| t)
+            .collect()
+    }
+}
+
+impl Config {
+    /// Excludes the toolchains given with `--skip` and `--skip-file` from the
+    /// bisection, along with their `commits` when bisecting CI builds, and
+    /// returns the remaining toolchains and the skipped ones.
+    ///
+    /// The first and last toolchains are kept, since they were already tested
+    /// to check the bounds of the range.
+    fn without_skipped(
+        &self,
+        toolchains: Vec<Toolchain>,
+        commits: &mut Vec<Commit>,
+    ) -> (Vec<Toolchain>, Vec<Toolchain>) {
+        // Commits outside of the bisection are only looked up when a range
+        // of commits to skip ends there.
+        let date_of = |sha: &str| match self.args.repo(&self.reporter).commit(sha) {
+            Ok(commit) => Some(commit.date),
+            Err(e) => {
+                warning!(self.reporter, "failed to look up {sha} to skip: {e:#}");
+                None
+            }
+        };
+        let mut skipped = skip::skipped(&self.skips, &toolchains, commits, &date_of);
+        if let Some(first) = skipped.first_mut() {
+            *first = false;
+        }
+        if let Some(last) = skipped.last_mut() {
+            *last = false;
+        }
+        if !commits.is_empty() {
+            let mut skipped = skipped.iter();
+            commits.retain(|_| !skipped.next().unwrap());
+        }
+        let (skipped, kept): (Vec<_>, Vec<_>) = toolchains
+            .into_iter()
+            .zip(skipped)
+            .partition(|(_, skipped)| *skipped);
+        let skipped: Vec<Toolchain> = skipped.into_iter().map(|(t, _)| t).collect();
+        for t in &skipped {
+            status!(self.reporter, "skipped {t}: skipped by user");
+            self.steps.lock().unwrap().push(Step::skipped_by_user(t));
+        }
+        (kept.into_iter().map(|(t, _)| t).collect(), skipped)
+    }
+}
+
+fn toolchains_between(cfg: &Config, a: ToolchainSpec, b: ToolchainSpec) -> Vec<Toolchain> {
+    match (a, b) {
+        (ToolchainSpec::Nightly { date: a }, ToolchainSpec::Nightly { date: b }) => {
+            let mut toolchains = Vec::new();
+            let mut date = a;
+            let mut std_targets = vec![cfg.args.host.clone(), cfg.target.clone()];
+            std_targets.sort();
+            std_targets.dedup();
+            while date <= b {
+                let t = Toolchain {
+                    spec: ToolchainSpec::Nightly { date },
+                    host: cfg.args.host.clone(),
+                    std_targets: std_targets.clone(),
+                };
+                toolchains.push(t);
+                date = date.succ();
+            }
+            toolchains
+        }
+        _ => unimplemented!(),
+    }
+}
+
+/// Parses an `--install` argument naming a stable release (like `1.62.0`) or
+/// a beta (like `beta-2022-08-01`).
+fn release_spec(s: &str) -> Option<ToolchainSpec> {
+    if let Some(date) = s.strip_prefix("beta-") {
+        let date = parse_to_utc_date(date).ok()?;
+        return Some(ToolchainSpec::Beta { date });
+    }
+    s.parse::<Version>()
+        .ok()
+        .map(|version| ToolchainSpec::Stable {
+            version: version.to_string(),
+        })
+}
+
+impl Config {
+    /// Bisects the stable releases between `--start` and `--end`, as with
+    /// `--by-release`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bounds are not releases, if the range does not show the
+    /// regression, or if the releases cannot be downloaded or tested.
+    pub fn bisect_releases(&self) -> anyhow::Result<BisectionResult> {
+        status!(self.reporter, "bisecting stable releases");
+        let (start, end) = match (&self.args.start, &self.args.end) {
+            (Some(Bound::Commit(start)), Some(Bound::Commit(end))) => {
+                (start.parse::<Version>()?, end.parse::<Version>()?)
+            }
+            _ => {
+                bail!("--by-release requires releases as bounds, e.g. --start=1.60.0 --end=1.64.0")
+            }
+        };
+        if end <= start {
+            bail!("end should be after start, got start: {start} and end {end}");
+        }
+
+        let dl_spec = DownloadParams::for_nightly(self);
+        self.reset_baselines();
+        let toolchains = releases_between(
+            &self.client,
+            self.mirrors.nightly(),
+            start,
+            end,
+            &self.reporter,
+        )?
+        .into_iter()
+        .map(|version| {
+            let mut t = Toolchain {
+                spec: ToolchainSpec::Stable {
+                    version: version.to_string(),
+                },
+                host: self.args.host.clone(),
+                std_targets: vec![self.args.host.clone(), self.target.clone()],
+            };
+            t.std_targets.sort();
+            t.std_targets.dedup();
+            t
+        })
+        .collect::<Vec<_>>();
+
+        status!(
+            self.reporter,
+            "checking the start range to verify it passes"
+        );
+        if self.install_and_test(&toolchains[0], &dl_spec)? == Satisfies::Yes {
+            bail!(
+                "the start of the range ({}) must not reproduce the regression",
+                toolchains[0]
+            );
+        }
+        let last = &toolchains[toolchains.len() - 1];
+        status!(
+            self.reporter,
+            "checking the end range to verify it does not pass"
+        );
+        if self.install_and_test(last, &dl_spec)? == Satisfies::No {
+            bail!("the end of the range ({last}) does not reproduce the regression");
+        }
+
+        let (found, confidence) = self.bisect_to_regression(&toolchains, &dl_spec);
+
+        Ok(BisectionResult {
+            searched: toolchains,
+            skipped: Vec::new(),
+            found,
+            dl_spec,
+            confidence,
+            steps: self.take_steps(),
+            commits: Vec::new(),
+        })
+    }
+
+    /// Narrows the bounds of the nightly bisection to the branch points of
+    /// the last release without the regression and the first one with it.
+    ///
+    /// Returns `false` if the regression cannot be narrowed down any further.
+    fn narrow_to_branch_points(
+        &mut self,
+        release_bisection_result: &BisectionResult,
+        found: bool,
+    ) -> anyhow::Result<bool> {
+        if !found {
+            return Ok(false);
+        }
+        let releases = &release_bisection_result.searched;
+        let found_at = release_bisection_result.found;
+        if found_at == 0 || found_at >= releases.len() {
+            status!(
+                self.reporter,
+                "the regression is in the first release of the range, so there is \
+                 no earlier branch point to look for it from"
+            );
+            return Ok(false);
+        }
+        let (previous, regressed) = (&releases[found_at - 1], &releases[found_at]);
+        let (previous, regressed) = match (&previous.spec, &regressed.spec) {
+            (ToolchainSpec::Stable { version: previous }, ToolchainSpec::Stable { version }) => {
+                (previous.clone(), version.parse::<Version>()?)
+            }
+            _ => bail!("expected stable releases to narrow down, found {previous} and {regressed}"),
+        };
+        if regressed.is_point_release() {
+            status!(
+                self.reporter,
+                "{regressed} is a point release, so the regression was backported \
+                 to it rather than landing in a nightly"
+            );
+            return Ok(false);
+        }
+        status!(
+            self.reporter,
+            "looking for regressed nightly between the branch points of {previous} and {regressed}"
+        );
+        self.args.start = Some(Bound::Commit(previous));
+        self.args.end = Some(Bound::Commit(regressed.to_string()));
+        let repo = self.args.repo(&self.reporter);
+        fixup_bounds(
+            &*repo,
+            &self.reporter,
+            &mut self.args.start,
+            &mut self.args.end,
+        )?;
+        Ok(true)
+    }
+}
+
+impl Config {
+    /// Bisects the CI builds of the commits between `--start` and `--end`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the range does not show the regression, or if the commits
+    /// cannot be looked up or their builds cannot be downloaded or tested.
+    pub fn bisect_ci(&self) -> anyhow::Result<BisectionResult> {
+        status!(self.reporter, "bisecting ci builds");
+        let start = if let Some(Bound::Commit(ref sha)) = self.args.start {
+            sha
+        } else {
+            EPOCH_COMMIT
+        };
+
+        let end = if let Some(Bound::Commit(ref sha)) = self.args.end {
+            sha
+        } else {
+            "origin/master"
+        };
+
+        status!(self.reporter, "starting at {}, ending at {}", start, end);
+
+        self.bisect_ci_via(start, end)
+    }
+
+    /// Bisects the CI builds of the commits from `start_sha` to `end_ref`,
+    /// which may be any reference the repository accessor understands.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Config::bisect_ci`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the repository accessor returns commits which are out of
+    /// order or do not end with `end_ref`.
+    pub fn bisect_ci_via(&self, start_sha: &str, end_ref: &str) -> anyhow::Result<BisectionResult> {
+        let access = self.args.repo(&self.reporter);
+        let end_sha = access.commit(end_ref)?.sha;
+        let commits = access.commits(start_sha, &end_sha)?;
+
+        assert_eq!(commits.last().expect("at least one commit").sha, end_sha);
+
+        commits.iter().zip(commits.iter().skip(1)).all(|(a, b)| {
+            let sorted_by_date = a.date <= b.date;
+            assert!(
+                sorted_by_date,
+                "commits must chronologically ordered,\
+                                 but {:?} comes after {:?}",
+                a, b
+            );
+            sorted_by_date
+        });
+
+        for (j, commit) in commits.iter().enumerate() {
+            status!(
+                self.reporter,
+                "  commit[{}] {}: {}",
+                j,
+                commit.date,
+                commit.summary.split('\n').next().unwrap()
+            )
+        }
+
+        self.bisect_ci_in_commits(start_sha, &end_sha, commits)
+    }
+
+    fn bisect_ci_in_commits(
+        &self,
+        start: &str,
+        end: &str,
+        mut commits: Vec<Commit>,
+    ) -> anyhow::Result<BisectionResult> {
+        let dl_spec = DownloadParams::for_ci(self);
+        self.reset_baselines();
+        commits.retain(|c| Utc::today() - c.date < Duration::days(167));
+
+        if commits.is_empty() {
+            bail!(
+                "no CI builds available between {} and {} within last 167 days",
+                start,
+                end
+            );
+        }
+
+        if let Some(c) = commits.last() {
+            if end != "origin/master" && !c.sha.starts_with(end) {
+                bail!("expected to end with {}, but ended with {}", end, c.sha);
+            }
+        }
+
+        status!(
+            self.reporter,
+            "validated commits found, specifying toolchains"
+        );
+        status!(self.reporter);
+
+        let toolchains = commits
+            .iter()
+            .map(|commit| {
+                let mut t = Toolchain {
+                    spec: ToolchainSpec::Ci {
+                        commit: commit.sha.clone(),
+                        alt: self.args.alt,
+                    },
+                    host: self.args.host.clone(),
+                    std_targets: vec![self.args.host.clone(), self.target.clone()],
+                };
+                t.std_targets.sort();
+                t.std_targets.dedup();
+                t
+            })
+            .collect::<Vec<_>>();
+
+        if !toolchains.is_empty() {
+            // validate commit at start of range
+            status!(
+                self.reporter,
+                "checking the start range to verify it passes"
+            );
+            let start_range_result = self.install_and_test(&toolchains[0], &dl_spec)?;
+            if start_range_result == Satisfies::Yes {
+                bail!(
+                    "the commit at the start of the range ({}) includes the regression",
+                    &toolchains[0]
+                );
+            }
+
+            // validate commit at end of range
+            status!(
+                self.reporter,
+                "checking the end range to verify it does not pass"
+            );
+            let end_range_result =
+                self.install_and_test(&toolchains[toolchains.len() - 1], &dl_spec)?;
+            if end_range_result == Satisfies::No {
+                bail!(
+                    "the commit at the end of the range ({}) does not reproduce the regression",
+                    &toolchains[toolchains.len() - 1]
+                );
+            }
+        }
+
+        let (toolchains, skipped) = self.without_skipped(toolchains, &mut commits);
+
+        let (found, confidence) = self.bisect_to_regression(&toolchains, &dl_spec);
+
+        Ok(BisectionResult {
+            searched: toolchains,
+            skipped,
+            found,
+            dl_spec,
+            confidence,
+            steps: self.take_steps(),
+            commits,
+        })
+    }
+}
+
+/// The outcome of one phase of a bisection.
+#[derive(Clone, Debug)]
+pub struct BisectionResult {
+    /// The toolchains of the range, in order.
+    pub searched: Vec<Toolchain>,
+    /// Toolchains of the range which were left out with `--skip` or
+    /// `--skip-file`.
+    pub skipped: Vec<Toolchain>,
+    /// Index in `searched` of the first toolchain with the regression.
+    pub found: usize,
+    pub(crate) dl_spec: DownloadParams,
+    /// Posterior confidence in `found`, for probabilistic bisections.
+    pub confidence: Option<f64>,
+    /// Toolchains tested during this phase, in the order they were tested.
+    pub(crate) steps: Vec<Step>,
+    /// The commits corresponding to `searched`, when bisecting CI artifacts.
+    pub commits: Vec<Commit>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Start and end date validations
+    #[test]
+    fn test_check_bounds_valid_bounds() {
+        let date1 = chrono::Utc::today().pred();
+        let date2 = chrono::Utc::today().pred();
+        assert!(check_bounds(&Some(Bound::Date(date1)), &Some(Bound::Date(date2))).is_ok());
+    }
+
+    #[test]
+    fn test_check_bounds_invalid_start_after_end() {
+        let start = chrono::Utc::today();
+        let end = chrono::Utc::today().pred();
+        assert!(check_bounds(&Some(Bound::Date(start)), &Some(Bound::Date(end))).is_err());
+    }
+
+    #[test]
+    fn test_check_bounds_invalid_start_after_current() {
+        let start = chrono::Utc::today().succ();
+        let end = chrono::Utc::today();
+        assert!(check_bounds(&Some(Bound::Date(start)), &Some(Bound::Date(end))).is_err());
+    }
+
+    #[test]
+    fn test_check_bounds_invalid_end_after_current() {
+        let start = chrono::Utc::today();
+        let end = chrono::Utc::today().succ();
+        assert!(check_bounds(&Some(Bound::Date(start)), &Some(Bound::Date(end))).is_err());
+    }
+
+    #[test]
+    fn test_nightly_finder_iterator() {
+        let start_date = Date::from_utc(NaiveDate::from_ymd(2019, 01, 01), Utc);
+
+        let iter = NightlyFinderIter::new(start_date);
+
+        for (date, i) in iter.zip([2, 4, 6, 8, 15, 22, 29, 36, 43, 50, 64, 78]) {
+            assert_eq!(start_date - Duration::days(i), date)
+        }
+    }
+
+    #[test]
+    fn test_validate_dir() {
+        let current_dir = ".";
+        assert!(validate_dir(current_dir).is_ok());
+        let main = "src/main.rs";
+        assert!(
+            validate_dir(main).is_err(),
+            "{}",
+            validate_dir(main).unwrap_err()
+        )
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let current_dir = ".";
+        assert!(validate_file(current_dir).is_err());
+        let main = "src/main.rs";
+        assert!(
+            validate_file(main).is_ok(),
+            "{}",
+            validate_dir(main).unwrap_err()
+        )
+    }
+
+    #[test]
+    fn rejects_perf_runs_which_cannot_be_significant() {
+        let opts = |runs: &str| {
+            Opts::try_parse_from([
+                "cargo-bisect-rustc",
+                "--regress=slower-than",
+                "--start=2022-08-01",
+                "--perf-runs",
+                runs,
+            ])
+            .unwrap()
+        };
+        let error = opts("2").validate_regress().unwrap_err().to_string();
+        assert!(
+            error.contains("smallest possible p-value is 0.12"),
+            "{error}"
+        );
+        assert!(opts("3").validate_regress().is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+//! Channel manifests published on the dist server (`channel-rust-*.toml`),
+//! which list the components available in a release along with the URLs and
+//! checksums of their tarballs.
+
+use std::collections::HashMap;
+
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use crate::toolchains::{get_text, DownloadError};
+use crate::Reporter;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Manifest {
+    pkg: HashMap<String, Package>,
+    /// Components which are published under a different name, such as
+    /// `llvm-tools` which is published as `llvm-tools-preview`.
+    #[serde(default)]
+    renames: HashMap<String, Rename>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    target: HashMap<String, Target>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Target {
+    available: bool,
+    url: Option<String>,
+    hash: Option<String>,
+    xz_url: Option<String>,
+    xz_hash: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Rename {
+    to: String,
+}
+
+/// A component tarball listed in a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Artifact {
+    pub(crate) url: String,
+    pub(crate) sha256: String,
+}
+
+impl Artifact {
+    /// The name of the tarball, e.g. `rustc-nightly-x86_64-unknown-linux-gnu`.
+    pub(crate) fn name(&self) -> &str {
+        let file = self.url.rsplit('/').next().unwrap_or(&self.url);
+        file.split(".tar.").next().unwrap_or(file)
+    }
+}
+
+impl Manifest {
+    /// Downloads the manifest at `url`. Very old nightlies have none.
+    pub(crate) fn fetch(
+        client: &Client,
+        url: &str,
+        attempts: u32,
+        reporter: &Reporter,
+    ) -> Result<Manifest, DownloadError> {
+        toml::from_str(&get_text(client, url, attempts, reporter)?).map_err(DownloadError::Manifest)
+    }
+
+    /// Looks up the tarball of `component` for `target`, following renames.
+    ///
+    /// Returns `None` if the component was not available on that day.
+    pub(crate) fn artifact(&self, component: &str, target: &str) -> Option<Artifact> {
+        let name = self
+            .renames
+            .get(component)
+            .map_or(component, |rename| &rename.to);
+        let package = self.pkg.get(name)?;
+        // Target-independent components like rust-src are listed for "*".
+        let target = package
+            .target
+            .get(target)
+            .or_else(|| package.target.get("*"))?;
+        if !target.available {
+            return None;
+        }
+        match (&target.xz_url, &target.xz_hash, &target.url, &target.hash) {
+            (Some(url), Some(hash), _, _) | (_, _, Some(url), Some(hash)) => Some(Artifact {
+                url: url.clone(),
+                sha256: hash.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+manifest-version = "2"
+date = "2022-08-01"
+
+[pkg.rustc.target.x86_64-unknown-linux-gnu]
+available = true
+url = "https://static.rust-lang.org/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.gz"
+hash = "1111111111111111111111111111111111111111111111111111111111111111"
+xz_url = "https://static.rust-lang.org/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz"
+xz_hash = "2222222222222222222222222222222222222222222222222222222222222222"
+
+[pkg.rust-src.target."*"]
+available = true
+url = "https://static.rust-lang.org/dist/2022-08-01/rust-src-nightly.tar.gz"
+hash = "3333333333333333333333333333333333333333333333333333333333333333"
+
+[pkg.llvm-tools-preview.target.x86_64-unknown-linux-gnu]
+available = true
+xz_url = "https://static.rust-lang.org/dist/2022-08-01/llvm-tools-nightly-x86_64-unknown-linux-gnu.tar.xz"
+xz_hash = "4444444444444444444444444444444444444444444444444444444444444444"
+
+[pkg.miri-preview.target.x86_64-unknown-linux-gnu]
+available = false
+
+[renames.llvm-tools]
+to = "llvm-tools-preview"
+
+[renames.miri]
+to = "miri-preview"
+"#;
+
+    #[test]
+    fn resolve_components() {
+        let manifest: Manifest = toml::from_str(MANIFEST).unwrap();
+        let host = "x86_64-unknown-linux-gnu";
+
+        let rustc = manifest.artifact("rustc", host).unwrap();
+        assert!(rustc.url.ends_with(".tar.xz"));
+        assert_eq!(rustc.name(), "rustc-nightly-x86_64-unknown-linux-gnu");
+        assert!(rustc.sha256.starts_with('2'));
+
+        assert_eq!(
+            manifest.artifact("rust-src", host).unwrap().name(),
+            "rust-src-nightly"
+        );
+        assert_eq!(
+            manifest.artifact("llvm-tools", host),
+            manifest.artifact("llvm-tools-preview", host)
+        );
+        assert!(manifest.artifact("llvm-tools", host).is_some());
+        assert_eq!(manifest.artifact("miri", host), None);
+        assert_eq!(manifest.artifact("rustc", "aarch64-apple-darwin"), None);
+        assert_eq!(manifest.artifact("rust-analyzer", host), None);
+    }
+}
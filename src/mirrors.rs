@@ -0,0 +1,198 @@
+//! The servers which toolchains are downloaded from: the dist server for
+//! nightlies and releases, and the CI artifact server for builds of single
+//! commits.
+//!
+//! Each can be replaced by a list of mirrors, which are tried in order, with
+//! `--nightly-server` and `--ci-server`, the corresponding environment
+//! variables, or in `CARGO_HOME/bisect-rustc.toml`:
+//!
+//! ```toml
+//! nightly-servers = ["https://mirror.example.com/dist", "https://static.rust-lang.org/dist"]
+//! ci-servers = ["https://mirror.example.com/rust-lang-ci2"]
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::Context;
+use log::debug;
+use serde::Deserialize;
+
+use crate::toolchains::DownloadError;
+use crate::Reporter;
+
+pub(crate) const NIGHTLY_SERVER: &str = "https://static.rust-lang.org/dist";
+pub(crate) const CI_SERVER: &str = "https://s3-us-west-1.amazonaws.com/rust-lang-ci2";
+
+/// Name of the config file in `CARGO_HOME`.
+pub(crate) const CONFIG_FILE: &str = "bisect-rustc.toml";
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    nightly_servers: Vec<String>,
+    #[serde(default)]
+    ci_servers: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Mirrors {
+    nightly: Vec<String>,
+    ci: Vec<String>,
+}
+
+impl Default for Mirrors {
+    fn default() -> Mirrors {
+        Mirrors {
+            nightly: vec![NIGHTLY_SERVER.to_string()],
+            ci: vec![CI_SERVER.to_string()],
+        }
+    }
+}
+
+impl Mirrors {
+    /// Uses the servers given on the command line or in the environment,
+    /// then those in `config_file`, then the official ones.
+    pub(crate) fn new(
+        nightly: &[String],
+        ci: &[String],
+        config_file: &Path,
+    ) -> anyhow::Result<Mirrors> {
+        let config = match fs::read_to_string(config_file) {
+            Ok(config) => toml::from_str(&config)
+                .with_context(|| format!("failed to parse {}", config_file.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", config_file.display()))
+            }
+        };
+        let choose = |given: &[String], configured: Vec<String>, default: &str| {
+            let servers = if !given.is_empty() {
+                given.to_vec()
+            } else if !configured.is_empty() {
+                configured
+            } else {
+                vec![default.to_string()]
+            };
+            servers
+                .into_iter()
+                .map(|server| server.trim_end_matches('/').to_string())
+                .collect()
+        };
+        Ok(Mirrors {
+            nightly: choose(nightly, config.nightly_servers, NIGHTLY_SERVER),
+            ci: choose(ci, config.ci_servers, CI_SERVER),
+        })
+    }
+
+    /// The dist servers, for nightlies and releases.
+    pub(crate) fn nightly(&self) -> &[String] {
+        &self.nightly
+    }
+
+    /// The CI artifact servers, for builds of single commits.
+    pub(crate) fn ci(&self) -> &[String] {
+        &self.ci
+    }
+}
+
+/// Returns the URLs of `path` on each of `servers`.
+pub(crate) fn urls(servers: &[String], path: &str) -> Vec<String> {
+    servers
+        .iter()
+        .map(|server| format!("{server}{path}"))
+        .collect()
+}
+
+/// Returns the URLs of a tarball listed in a channel manifest on each of the
+/// dist `servers`, since manifests always point to the official one.
+pub(crate) fn artifact_urls(servers: &[String], url: &str) -> Vec<String> {
+    match url.strip_prefix(NIGHTLY_SERVER) {
+        Some(path) => urls(servers, path),
+        None => vec![url.to_string()],
+    }
+}
+
+/// Calls `f` with each of `urls` in turn until it succeeds, and returns the
+/// last error if it never does.
+pub(crate) fn first_available<T, F>(
+    urls: &[String],
+    reporter: &Reporter,
+    mut f: F,
+) -> Result<T, DownloadError>
+where
+    F: FnMut(&str) -> Result<T, DownloadError>,
+{
+    let (last, rest) = urls
+        .split_last()
+        .expect("at least one server is configured");
+    for url in rest {
+        match f(url) {
+            Ok(t) => return Ok(t),
+            Err(DownloadError::NotFound(_)) => {
+                debug!("<{}> not found, trying the next mirror", url)
+            }
+            Err(e) => warning!(reporter, "{e}, trying the next mirror"),
+        }
+    }
+    f(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join(CONFIG_FILE);
+        let given = vec!["https://given.example.com/dist/".to_string()];
+
+        assert_eq!(
+            Mirrors::new(&[], &[], &config_file).unwrap(),
+            Mirrors::default()
+        );
+
+        fs::write(
+            &config_file,
+            "nightly-servers = ['https://a.example.com/dist', 'https://b.example.com/dist']",
+        )
+        .unwrap();
+        let mirrors = Mirrors::new(&[], &[], &config_file).unwrap();
+        assert_eq!(
+            mirrors.nightly(),
+            ["https://a.example.com/dist", "https://b.example.com/dist"]
+        );
+        assert_eq!(mirrors.ci(), [CI_SERVER]);
+
+        let mirrors = Mirrors::new(&given, &[], &config_file).unwrap();
+        assert_eq!(mirrors.nightly(), ["https://given.example.com/dist"]);
+
+        fs::write(&config_file, "nightly-server = 'typo'").unwrap();
+        assert!(Mirrors::new(&[], &[], &config_file).is_err());
+    }
+
+    #[test]
+    fn rebases_artifacts() {
+        let servers = vec![
+            "https://mirror.example.com/dist".to_string(),
+            NIGHTLY_SERVER.to_string(),
+        ];
+        assert_eq!(
+            artifact_urls(
+                &servers,
+                "https://static.rust-lang.org/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz"
+            ),
+            [
+                "https://mirror.example.com/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz",
+                "https://static.rust-lang.org/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz",
+            ]
+        );
+        assert_eq!(
+            artifact_urls(&servers, "https://elsewhere.example.com/rustc.tar.xz"),
+            ["https://elsewhere.example.com/rustc.tar.xz"]
+        );
+    }
+}
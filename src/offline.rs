@@ -0,0 +1,125 @@
+//! Downloading everything that a bisection needs ahead of time, on a machine
+//! with network access, into a directory which can be carried over to one
+//! without.
+//!
+//! The directory holds the files of the dist and CI servers at the same paths,
+//! so that it can be used as a `file://` mirror of both, along with the commits
+//! of the range for `--access=offline`:
+//!
+//! ```text
+//! DIR/dist/2022-08-01/channel-rust-nightly.toml
+//! DIR/dist/2022-08-01/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz
+//! DIR/ci/rustc-builds/<commit>/rustc-nightly-x86_64-unknown-linux-gnu.tar.xz
+//! DIR/commits.txt
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+
+use crate::repo_access::{self, COMMITS_FILE};
+use crate::toolchains::{DownloadParams, InstallError, Toolchain, ToolchainSpec};
+use crate::{get_end_date, get_start_date, toolchains_between, Bound, Commit, Config};
+
+/// Downloads the toolchains between `--start` and `--end` into `dir`: the
+/// nightlies unless bisecting by commit, and the CI builds of every commit in
+/// the range which are still available.
+pub(crate) fn prefetch(cfg: &Config, dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let dir = dir.canonicalize()?;
+
+    let start = cfg.args.start.as_ref().expect("--start is required");
+    let end = match cfg.args.end {
+        Some(ref end) => end.clone(),
+        None if cfg.is_commit => Bound::Commit("origin/master".to_string()),
+        None => Bound::Date(get_end_date(cfg)),
+    };
+    if !cfg.is_commit {
+        let dl_params = DownloadParams::for_nightly(cfg);
+        let nightlies = toolchains_between(
+            cfg,
+            ToolchainSpec::Nightly {
+                date: get_start_date(cfg),
+            },
+            ToolchainSpec::Nightly {
+                date: get_end_date(cfg),
+            },
+        );
+        for t in &nightlies {
+            mirror(cfg, t, &dl_params, &dir.join("dist"))?;
+        }
+    }
+
+    let repo = cfg.args.repo(&cfg.reporter);
+    let start = start.sha(cfg.mirrors.nightly(), &cfg.reporter)?;
+    let end = repo
+        .commit(&end.sha(cfg.mirrors.nightly(), &cfg.reporter)?)?
+        .sha;
+    let commits = repo.commits(&start, &end)?;
+    repo_access::write_commits(&dir.join(COMMITS_FILE), &commits)
+        .context("failed to write the commits of the range")?;
+    prefetch_ci_builds(cfg, &commits, &dir.join("ci"))?;
+
+    status!(
+        cfg.reporter,
+        "downloaded the range into {0}, bisect it without network access by copying it to \
+         the same path and passing\n  --nightly-server=file://{0}/dist \
+         --ci-server=file://{0}/ci --access=offline --commits-file={0}/{COMMITS_FILE}",
+        dir.display()
+    );
+    Ok(())
+}
+
+fn prefetch_ci_builds(cfg: &Config, commits: &[Commit], dir: &Path) -> anyhow::Result<()> {
+    let dl_params = DownloadParams::for_ci(cfg);
+    let dir = dir.join(if cfg.args.alt {
+        "rustc-builds-alt"
+    } else {
+        "rustc-builds"
+    });
+    let available: Vec<&Commit> = commits
+        .iter()
+        .filter(|c| Utc::today() - c.date < Duration::days(167))
+        .collect();
+    if available.len() < commits.len() {
+        status!(
+            cfg.reporter,
+            "skipping the CI builds of {} commits, which are more than 167 days old",
+            commits.len() - available.len()
+        );
+    }
+    for commit in available {
+        let mut t = Toolchain {
+            spec: ToolchainSpec::Ci {
+                commit: commit.sha.clone(),
+                alt: cfg.args.alt,
+            },
+            host: cfg.args.host.clone(),
+            std_targets: vec![cfg.args.host.clone(), cfg.target.clone()],
+        };
+        t.std_targets.sort();
+        t.std_targets.dedup();
+        mirror(cfg, &t, &dl_params, &dir)?;
+    }
+    Ok(())
+}
+
+/// Downloads `t` into `dir`, or skips it if it is not available, like a
+/// bisection would.
+fn mirror(
+    cfg: &Config,
+    t: &Toolchain,
+    dl_params: &DownloadParams,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    match t.mirror(&cfg.client, dl_params, dir) {
+        Ok(()) => Ok(()),
+        Err(e @ (InstallError::NotFound { .. } | InstallError::Unavailable { .. })) => {
+            status!(cfg.reporter, "skipping {t}: {e}");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
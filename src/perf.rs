@@ -0,0 +1,411 @@
+//! Measurements for bisecting performance regressions with
+//! `--regress=slower-than`, and the statistics to decide whether a toolchain
+//! is slower than the start of the range.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Metric {
+    // Wall time of the test command.
+    WallTime,
+    // Instructions executed by the test command and its children, counted
+    // with `perf stat` (Linux only).
+    Instructions,
+    // Peak resident memory of the test command and its children (Linux
+    // only).
+    MaxRss,
+}
+
+impl Metric {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Metric::WallTime => "wall-time",
+            Metric::Instructions => "instructions",
+            Metric::MaxRss => "max-rss",
+        }
+    }
+
+    fn format(self, value: f64) -> String {
+        match self {
+            Metric::WallTime => format!("{value:.2}s"),
+            Metric::Instructions => format!("{value:.4e}"),
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Metric::MaxRss => crate::command::format_bytes(value as u64),
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StatTest {
+    // Only compare the medians against the threshold.
+    None,
+    // Welch's t-test, which assumes roughly normally distributed samples.
+    Welch,
+    // Mann-Whitney U test, which makes no assumption about the distribution.
+    MannWhitney,
+}
+
+impl StatTest {
+    /// The smallest p-value the test can give with `runs` measurements on
+    /// each side, if it has a floor. The Mann-Whitney U test only looks at
+    /// ranks, so a few runs cannot be significant however large the slowdown.
+    pub(crate) fn smallest_p_value(self, runs: u32) -> Option<f64> {
+        match self {
+            StatTest::None | StatTest::Welch => None,
+            StatTest::MannWhitney => {
+                let baseline: Vec<f64> = (0..runs).map(f64::from).collect();
+                let slower: Vec<f64> = (runs..2 * runs).map(f64::from).collect();
+                Some(mann_whitney_p_value(&baseline, &slower))
+            }
+        }
+    }
+}
+
+/// How a toolchain compares to the baseline.
+pub(crate) struct Comparison {
+    /// Relative change of the median, e.g. `0.1` for 10% slower.
+    pub(crate) change: f64,
+    /// One-sided p-value of the toolchain being slower, if a test was used.
+    pub(crate) p_value: Option<f64>,
+    pub(crate) slower: bool,
+}
+
+impl Comparison {
+    /// Compares the `candidate` samples against the `baseline` ones, treating
+    /// the candidate as slower if its median is more than `threshold` percent
+    /// higher, and `test` finds the difference significant at level `alpha`.
+    pub(crate) fn new(
+        baseline: &[f64],
+        candidate: &[f64],
+        threshold: f64,
+        test: StatTest,
+        alpha: f64,
+    ) -> Comparison {
+        let change = median(candidate) / median(baseline) - 1.0;
+        let p_value = match test {
+            StatTest::None => None,
+            StatTest::Welch => Some(welch_p_value(baseline, candidate)),
+            StatTest::MannWhitney => Some(mann_whitney_p_value(baseline, candidate)),
+        };
+        Comparison {
+            change,
+            p_value,
+            slower: change * 100.0 > threshold
+                && match p_value {
+                    Some(p) => p < alpha,
+                    None => true,
+                },
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+.1}% vs. baseline", self.change * 100.0)?;
+        if let Some(p) = self.p_value {
+            write!(f, ", p = {p:.3}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes the distribution of `samples` of `metric`.
+pub(crate) fn describe(metric: Metric, samples: &[f64]) -> String {
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    format!(
+        "{} median {} (min {}, max {}) over {} runs",
+        metric.name(),
+        metric.format(median(samples)),
+        metric.format(min),
+        metric.format(max),
+        samples.len()
+    )
+}
+
+/// Wraps `cmd` in `perf stat`, writing the number of instructions it executed
+/// to `output`.
+pub(crate) fn perf_stat(cmd: &Command, output: &Path) -> Command {
+    let mut perf = Command::new("perf");
+    perf.args(["stat", "-x,", "-e", "instructions:u", "-o"])
+        .arg(output)
+        .arg("--")
+        .arg(cmd.get_program())
+        .args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => perf.env(key, value),
+            None => perf.env_remove(key),
+        };
+    }
+    perf
+}
+
+/// Reads the instruction count from the CSV output of `perf stat`.
+pub(crate) fn read_perf_stat(output: &Path) -> io::Result<f64> {
+    parse_perf_stat(&fs::read_to_string(output)?).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no instruction count in {}", output.display()),
+        )
+    })
+}
+
+fn parse_perf_stat(s: &str) -> Option<f64> {
+    s.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let mut fields = line.split(',');
+            let count = fields.next()?;
+            let event = fields.nth(1)?;
+            if event.starts_with("instructions") {
+                count.parse().ok()
+            } else {
+                None
+            }
+        })
+}
+
+fn median(samples: &[f64]) -> f64 {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // Both are the middle element if there is an odd number of samples.
+    let (low, high) = ((sorted.len() - 1) / 2, sorted.len() / 2);
+    sorted[low] / 2.0 + sorted[high] / 2.0
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance)
+}
+
+/// One-sided p-value of Welch's t-test for `b` having a higher mean than `a`.
+#[allow(clippy::cast_precision_loss, clippy::many_single_char_names)]
+fn welch_p_value(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, var_a) = mean_and_variance(a);
+    let (mean_b, var_b) = mean_and_variance(b);
+    let (se_a, se_b) = (var_a / a.len() as f64, var_b / b.len() as f64);
+    let se = se_a + se_b;
+    if se <= 0.0 {
+        return if mean_b > mean_a { 0.0 } else { 1.0 };
+    }
+    let t = (mean_b - mean_a) / se.sqrt();
+    let df =
+        se.powi(2) / (se_a.powi(2) / (a.len() - 1) as f64 + se_b.powi(2) / (b.len() - 1) as f64);
+    // P(T > |t|) for Student's t distribution with `df` degrees of freedom.
+    let tail = 0.5 * incomplete_beta(df / 2.0, 0.5, df / (df + t * t));
+    if t > 0.0 {
+        tail
+    } else {
+        1.0 - tail
+    }
+}
+
+/// One-sided p-value of the Mann-Whitney U test for `b` being stochastically
+/// greater than `a`, using the normal approximation with a tie correction.
+#[allow(clippy::cast_precision_loss, clippy::many_single_char_names)]
+fn mann_whitney_p_value(a: &[f64], b: &[f64]) -> f64 {
+    let u: f64 = b
+        .iter()
+        .map(|y| {
+            a.iter()
+                .map(|x| match y.total_cmp(x) {
+                    std::cmp::Ordering::Greater => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Less => 0.0,
+                })
+                .sum::<f64>()
+        })
+        .sum();
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let n = na + nb;
+
+    let mut all: Vec<f64> = a.iter().chain(b).copied().collect();
+    all.sort_by(f64::total_cmp);
+    // Sum of t^3 - t over groups of t tied samples.
+    let mut ties = 0.0;
+    let mut i = 0;
+    while i < all.len() {
+        let run = all[i..]
+            .iter()
+            .take_while(|x| x.total_cmp(&all[i]).is_eq())
+            .count();
+        ties += (run as f64).powi(3) - run as f64;
+        i += run;
+    }
+
+    let mean = na * nb / 2.0;
+    let sd = (na * nb / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)))).sqrt();
+    if sd <= 0.0 {
+        return if u > mean { 0.0 } else { 1.0 };
+    }
+    // Continuity correction.
+    let z = (u - mean - 0.5) / sd;
+    1.0 - normal_cdf(z)
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, accurate to 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, evaluated with the
+/// continued fraction from Numerical Recipes.
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only for x < (a + 1) / (a + b + 2).
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+#[allow(clippy::many_single_char_names)]
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..200 {
+        let m = f64::from(m);
+        let m2 = 2.0 * m;
+        for numerator in [
+            m * (b - m) * x / ((a + m2 - 1.0) * (a + m2)),
+            -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Lanczos approximation of `ln(Γ(x))`.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut y = x;
+    let series = COEFFICIENTS.iter().fold(1.000_000_000_190_015, |sum, c| {
+        y += 1.0;
+        sum + c / y
+    });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE: [f64; 5] = [10.1, 9.9, 10.0, 10.2, 9.8];
+
+    #[test]
+    fn detects_slowdown() {
+        let slower = [11.2, 11.0, 11.4, 10.9, 11.1];
+        for test in [StatTest::None, StatTest::Welch, StatTest::MannWhitney] {
+            let comparison = Comparison::new(&BASELINE, &slower, 5.0, test, 0.05);
+            assert!(comparison.slower, "{test:?}: {comparison}");
+            assert!((comparison.change - 0.11).abs() < 1e-9);
+        }
+        // Below the threshold.
+        let comparison = Comparison::new(&BASELINE, &slower, 20.0, StatTest::Welch, 0.05);
+        assert!(!comparison.slower);
+    }
+
+    #[test]
+    fn ignores_noise() {
+        let noisy = [9.0, 13.0, 10.5, 8.5, 12.0];
+        for test in [StatTest::Welch, StatTest::MannWhitney] {
+            let comparison = Comparison::new(&BASELINE, &noisy, 2.0, test, 0.05);
+            assert!(!comparison.slower, "{test:?}: {comparison}");
+        }
+        let faster = [9.0, 9.1, 8.9, 9.2, 9.0];
+        let comparison = Comparison::new(&BASELINE, &faster, 0.0, StatTest::Welch, 0.05);
+        assert!(!comparison.slower);
+        assert!(comparison.p_value.unwrap() > 0.95);
+    }
+
+    #[test]
+    fn smallest_p_values() {
+        let smallest = |runs| StatTest::MannWhitney.smallest_p_value(runs).unwrap();
+        assert!(smallest(2) > 0.1);
+        assert!(smallest(3) < 0.05);
+        assert!(smallest(5) < smallest(3));
+        assert_eq!(StatTest::Welch.smallest_p_value(2), None);
+    }
+
+    #[test]
+    fn p_values() {
+        // t = 2.0 with 10 degrees of freedom.
+        assert!((0.5 * incomplete_beta(5.0, 0.5, 10.0 / 14.0) - 0.036_694).abs() < 1e-5);
+        assert!((normal_cdf(1.959_964) - 0.975).abs() < 1e-6);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn parse_instructions() {
+        let output = "\
+# started on Mon Aug  1 10:00:00 2022
+
+12345678901,,instructions:u,1000000000,100.00,,
+";
+        assert_eq!(parse_perf_stat(output), Some(12_345_678_901.0));
+        assert_eq!(
+            parse_perf_stat("<not counted>,,instructions:u,0,0.00,,\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,469 @@
+//! Deciding whether a run of the test shows the regression.
+//!
+//! `cargo bisect-rustc` builds a [`Predicate`] out of `--regress` or
+//! `--regress-if`, `--lint` and the `--regress-*-matches` patterns. Library
+//! users can supply their own instead, such as a closure:
+//!
+//! ```
+//! use cargo_bisect_rustc::{Predicate, RegressOn, TestInput, Verdict};
+//!
+//! let slow = |input: &TestInput<'_>| {
+//!     if input.duration.as_secs() > 60 {
+//!         Verdict::Regressed
+//!     } else {
+//!         Verdict::Baseline
+//!     }
+//! };
+//! let predicate = RegressOn::IceAlone.or(slow);
+//! ```
+
+use std::iter::Peekable;
+use std::process::ExitStatus;
+use std::str::{FromStr, SplitWhitespace};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use clap::ArgEnum;
+use regex::Regex;
+
+use crate::toolchains::Toolchain;
+use crate::RegressOn;
+
+/// What a predicate decides about a run of the test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The toolchain has the regression.
+    Regressed,
+    /// The toolchain behaves like the start of the range.
+    Baseline,
+    /// The run says nothing about the regression, e.g. because the test
+    /// failed for an unrelated reason, so the toolchain counts as untested.
+    Skip,
+}
+
+/// A finished run of the test command.
+#[derive(Debug)]
+pub struct TestInput<'a> {
+    pub toolchain: &'a Toolchain,
+    pub status: ExitStatus,
+    /// Output of the test command, which is only captured if the predicate
+    /// [needs it](Predicate::needs_output).
+    pub stdout: &'a str,
+    pub stderr: &'a str,
+    /// How long the test command ran for.
+    pub duration: Duration,
+}
+
+/// Decides whether a run of the test shows the regression.
+///
+/// Predicates are combined with [`and`](Predicate::and),
+/// [`or`](Predicate::or) and [`not`](Predicate::not). A skipped run is
+/// skipped by the combination too, unless the other side decides it alone.
+pub trait Predicate: Send + Sync {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict;
+
+    /// Whether the verdict depends on the output of the test command, which
+    /// then has to be captured instead of going straight to the terminal.
+    fn needs_output(&self) -> bool {
+        true
+    }
+
+    /// Regressed if both predicates are, baseline if either is.
+    fn and<P: Predicate>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Regressed if either predicate is, baseline if both are.
+    fn or<P: Predicate>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Swaps regressed and baseline.
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+impl<F> Predicate for F
+where
+    F: Fn(&TestInput<'_>) -> Verdict + Send + Sync,
+{
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        self(input)
+    }
+}
+
+impl Predicate for Box<dyn Predicate> {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        (**self).verdict(input)
+    }
+
+    fn needs_output(&self) -> bool {
+        (**self).needs_output()
+    }
+}
+
+/// See [`Predicate::and`].
+#[derive(Clone, Debug)]
+pub struct And<A, B>(A, B);
+
+impl<A: Predicate, B: Predicate> Predicate for And<A, B> {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        let a = self.0.verdict(input);
+        if a == Verdict::Baseline {
+            return a;
+        }
+        match (a, self.1.verdict(input)) {
+            (_, Verdict::Baseline) => Verdict::Baseline,
+            (Verdict::Regressed, Verdict::Regressed) => Verdict::Regressed,
+            _ => Verdict::Skip,
+        }
+    }
+
+    fn needs_output(&self) -> bool {
+        self.0.needs_output() || self.1.needs_output()
+    }
+}
+
+/// See [`Predicate::or`].
+#[derive(Clone, Debug)]
+pub struct Or<A, B>(A, B);
+
+impl<A: Predicate, B: Predicate> Predicate for Or<A, B> {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        let a = self.0.verdict(input);
+        if a == Verdict::Regressed {
+            return a;
+        }
+        match (a, self.1.verdict(input)) {
+            (_, Verdict::Regressed) => Verdict::Regressed,
+            (Verdict::Baseline, Verdict::Baseline) => Verdict::Baseline,
+            _ => Verdict::Skip,
+        }
+    }
+
+    fn needs_output(&self) -> bool {
+        self.0.needs_output() || self.1.needs_output()
+    }
+}
+
+/// See [`Predicate::not`].
+#[derive(Clone, Debug)]
+pub struct Not<P>(P);
+
+impl<P: Predicate> Predicate for Not<P> {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        match self.0.verdict(input) {
+            Verdict::Regressed => Verdict::Baseline,
+            Verdict::Baseline => Verdict::Regressed,
+            Verdict::Skip => Verdict::Skip,
+        }
+    }
+
+    fn needs_output(&self) -> bool {
+        self.0.needs_output()
+    }
+}
+
+/// The status of the test command decides, as described for each variant.
+/// `--regress=slower-than` and `--regress=peak-memory` are measured
+/// separately, and treat a test which fails outright as regressed.
+impl Predicate for RegressOn {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        let saw_ice = input.stderr.contains("error: internal compiler error")
+            || input.stderr.contains("' has overflowed its stack");
+
+        let regressed = match (self, input.status.success()) {
+            (RegressOn::ErrorStatus | RegressOn::SlowerThan | RegressOn::PeakMemory, success) => {
+                !success
+            }
+            (RegressOn::SuccessStatus, success) => success,
+            (RegressOn::NonCleanError, true) => true,
+            (RegressOn::IceAlone, _) | (RegressOn::NonCleanError, false) => saw_ice,
+            (RegressOn::NotIce, _) => !saw_ice,
+        };
+        if regressed {
+            Verdict::Regressed
+        } else {
+            Verdict::Baseline
+        }
+    }
+
+    fn needs_output(&self) -> bool {
+        match self {
+            RegressOn::ErrorStatus
+            | RegressOn::SuccessStatus
+            | RegressOn::SlowerThan
+            | RegressOn::PeakMemory => false,
+            RegressOn::NonCleanError | RegressOn::IceAlone | RegressOn::NotIce => true,
+        }
+    }
+}
+
+/// Regressed if clippy emits the given lint, as with `--lint`.
+#[derive(Clone, Debug)]
+pub struct EmitsLint(pub String);
+
+impl Predicate for EmitsLint {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        if emits_lint(input.stderr, &self.0) {
+            Verdict::Regressed
+        } else {
+            Verdict::Baseline
+        }
+    }
+}
+
+/// Whether clippy's output mentions `lint`, either in the note about its
+/// level (`#[warn(clippy::needless_return)]`) or in the link to its
+/// documentation.
+fn emits_lint(stderr: &str, lint: &str) -> bool {
+    let lint = lint.trim_start_matches("clippy::");
+    [format!("clippy::{lint}"), format!("index.html#{lint}")]
+        .iter()
+        .any(|needle| {
+            stderr.match_indices(needle.as_str()).any(|(i, _)| {
+                // Don't mistake `needless_return` for `needless_return_with_question_mark`.
+                !stderr[i + needle.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            })
+        })
+}
+
+/// Regressed if the output of the test command matches the regex, as with
+/// `--regress-if-stdout-matches` and `--regress-if-stderr-matches`.
+#[derive(Clone, Debug)]
+pub enum OutputMatches {
+    Stdout(Regex),
+    Stderr(Regex),
+}
+
+impl Predicate for OutputMatches {
+    fn verdict(&self, input: &TestInput<'_>) -> Verdict {
+        let matches = match self {
+            OutputMatches::Stdout(regex) => regex.is_match(input.stdout),
+            OutputMatches::Stderr(regex) => regex.is_match(input.stderr),
+        };
+        if matches {
+            Verdict::Regressed
+        } else {
+            Verdict::Baseline
+        }
+    }
+}
+
+/// A combination of `--regress` values given with `--regress-if`, such as
+/// `ice or (error and not non-error)`.
+#[derive(Clone, Debug)]
+pub(crate) enum Expr {
+    On(RegressOn),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+type Tokens<'a> = Peekable<SplitWhitespace<'a>>;
+
+impl Expr {
+    pub(crate) fn to_predicate(&self) -> Box<dyn Predicate> {
+        match self {
+            Expr::On(regress) => Box::new(*regress),
+            Expr::Not(e) => Box::new(e.to_predicate().not()),
+            Expr::And(a, b) => Box::new(a.to_predicate().and(b.to_predicate())),
+            Expr::Or(a, b) => Box::new(a.to_predicate().or(b.to_predicate())),
+        }
+    }
+
+    fn parse_or(tokens: &mut Tokens<'_>) -> anyhow::Result<Expr> {
+        let mut expr = Expr::parse_and(tokens)?;
+        while tokens.next_if_eq(&"or").is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(Expr::parse_and(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(tokens: &mut Tokens<'_>) -> anyhow::Result<Expr> {
+        let mut expr = Expr::parse_not(tokens)?;
+        while tokens.next_if_eq(&"and").is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(Expr::parse_not(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(tokens: &mut Tokens<'_>) -> anyhow::Result<Expr> {
+        match tokens.next() {
+            Some("not") => Ok(Expr::Not(Box::new(Expr::parse_not(tokens)?))),
+            Some("(") => {
+                let expr = Expr::parse_or(tokens)?;
+                if tokens.next() != Some(")") {
+                    bail!("missing `)`");
+                }
+                Ok(expr)
+            }
+            Some(name) => match <RegressOn as ArgEnum>::from_str(name, false) {
+                // These are measured, rather than decided by a single run.
+                Ok(RegressOn::SlowerThan | RegressOn::PeakMemory) => {
+                    bail!("`{name}` can only be used with --regress")
+                }
+                Ok(regress) => Ok(Expr::On(regress)),
+                Err(_) => Err(anyhow!(
+                    "`{name}` is not one of error, success, ice, non-ice and non-error"
+                )),
+            },
+            None => bail!("expected a --regress value"),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Expr> {
+        let s = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = s.split_whitespace().peekable();
+        let expr = Expr::parse_or(&mut tokens)?;
+        match tokens.next() {
+            Some(token) => bail!("unexpected `{token}`"),
+            None => Ok(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_args;
+    use crate::toolchains::ToolchainSpec;
+
+    fn verdict(predicate: &dyn Predicate, success: bool, stdout: &str, stderr: &str) -> Verdict {
+        let toolchain = Toolchain {
+            spec: ToolchainSpec::Stable {
+                version: "1.62.0".to_string(),
+            },
+            host: "x86_64-unknown-linux-gnu".to_string(),
+            std_targets: Vec::new(),
+        };
+        let status = std::process::Command::new(if success { "true" } else { "false" })
+            .status()
+            .unwrap();
+        predicate.verdict(&TestInput {
+            toolchain: &toolchain,
+            status,
+            stdout,
+            stderr,
+            duration: Duration::ZERO,
+        })
+    }
+
+    #[test]
+    fn test_emits_lint() {
+        let stderr = "\
+warning: unneeded `return` statement
+ --> src/main.rs:2:5
+  |
+  = note: `#[warn(clippy::needless_return)]` on by default
+  = help: for further information visit https://rust-lang.github.io/rust-clippy/master/index.html#needless_return
+";
+        assert!(emits_lint(stderr, "needless_return"));
+        assert!(emits_lint(stderr, "clippy::needless_return"));
+        assert!(!emits_lint(stderr, "needless"));
+        assert!(!emits_lint(stderr, "let_and_return"));
+        assert!(!emits_lint(
+            "https://rust-lang.github.io/rust-clippy/master/index.html#needless_return_with_question_mark",
+            "needless_return"
+        ));
+    }
+
+    #[test]
+    fn test_output_patterns() {
+        let args: Vec<String> = [
+            "cargo-bisect-rustc",
+            "--regress-if-stderr-matches",
+            "error\\[E0308\\]",
+            "--regress-unless-stdout-matches",
+            "^skipped",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        let predicate = parse_args(&args).unwrap().predicate();
+        assert!(predicate.needs_output());
+        let e0308 = "error[E0308]: mismatched types";
+        assert_eq!(verdict(&predicate, false, "", e0308), Verdict::Regressed);
+        assert_eq!(
+            verdict(
+                &predicate,
+                false,
+                "",
+                "error[E0277]: trait bound not satisfied"
+            ),
+            Verdict::Baseline
+        );
+        assert_eq!(
+            verdict(&predicate, false, "skipped", e0308),
+            Verdict::Baseline
+        );
+        assert_eq!(verdict(&predicate, true, "", e0308), Verdict::Baseline);
+
+        let predicate = parse_args(&["cargo-bisect-rustc".to_string()])
+            .unwrap()
+            .predicate();
+        assert!(!predicate.needs_output());
+        assert_eq!(verdict(&predicate, false, "", ""), Verdict::Regressed);
+    }
+
+    #[test]
+    fn combines_predicates() {
+        let ice = "error: internal compiler error: unexpected panic";
+        let expr: Expr = "success or (error and ice)".parse().unwrap();
+        let predicate = expr.to_predicate();
+        assert!(predicate.needs_output());
+        assert_eq!(verdict(&predicate, true, "", ""), Verdict::Regressed);
+        assert_eq!(verdict(&predicate, false, "", ice), Verdict::Regressed);
+        assert_eq!(verdict(&predicate, false, "", ""), Verdict::Baseline);
+        let expr: Expr = "not ice and not success".parse().unwrap();
+        assert_eq!(
+            verdict(&expr.to_predicate(), false, "", ""),
+            Verdict::Regressed
+        );
+        assert_eq!(
+            verdict(&expr.to_predicate(), false, "", ice),
+            Verdict::Baseline
+        );
+
+        for invalid in [
+            "",
+            "ice or",
+            "(ice",
+            "ice)",
+            "not",
+            "slower-than",
+            "ice xor error",
+        ] {
+            assert!(invalid.parse::<Expr>().is_err(), "{invalid}");
+        }
+
+        let skip = |_: &TestInput<'_>| Verdict::Skip;
+        assert_eq!(
+            verdict(&RegressOn::ErrorStatus.and(skip), true, "", ""),
+            Verdict::Baseline
+        );
+        assert_eq!(
+            verdict(&RegressOn::ErrorStatus.and(skip), false, "", ""),
+            Verdict::Skip
+        );
+        assert_eq!(
+            verdict(&RegressOn::ErrorStatus.or(skip), false, "", ""),
+            Verdict::Regressed
+        );
+        assert_eq!(verdict(&Not(skip), false, "", ""), Verdict::Skip);
+    }
+}
@@ -0,0 +1,123 @@
+//! Installing the toolchains which the bisection may test next in the
+//! background, while the current one is being tested, so that downloading
+//! them overlaps with running the test.
+//!
+//! Each step prefetches both toolchains which the next step may test, and the
+//! one which turns out not to be needed is removed again.
+
+use std::collections::HashSet;
+use std::thread::{self, JoinHandle};
+
+use log::debug;
+use reqwest::blocking::Client;
+
+use crate::toolchains::{DownloadParams, InstallError, Toolchain};
+
+struct Prefetch {
+    toolchain: Toolchain,
+    handle: JoinHandle<Result<(), InstallError>>,
+}
+
+impl Prefetch {
+    /// Waits for the installation to finish, and returns the toolchain if it
+    /// succeeded.
+    fn join(self) -> Option<Toolchain> {
+        match self.handle.join() {
+            Ok(Ok(())) => Some(self.toolchain),
+            Ok(Err(_)) => None,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+pub(crate) struct Prefetcher {
+    client: Client,
+    dl_params: DownloadParams,
+    /// Installations of toolchains which may still be tested.
+    pending: Vec<Prefetch>,
+    /// Installations of toolchains which are no longer needed, to be removed
+    /// once they finish.
+    abandoned: Vec<Prefetch>,
+    /// Toolchains which were prefetched, so as to not install them twice.
+    started: HashSet<String>,
+}
+
+impl Prefetcher {
+    pub(crate) fn new(client: &Client, dl_params: &DownloadParams) -> Prefetcher {
+        Prefetcher {
+            client: client.clone(),
+            dl_params: dl_params.in_background(),
+            pending: Vec::new(),
+            abandoned: Vec::new(),
+            started: HashSet::new(),
+        }
+    }
+
+    /// Starts installing `candidates` in the background while `next` is
+    /// tested, and abandons the other installations.
+    ///
+    /// Returns the abandoned toolchains which were installed by now, which
+    /// the caller should remove.
+    pub(crate) fn prefetch(
+        &mut self,
+        next: &Toolchain,
+        candidates: &[&Toolchain],
+    ) -> Vec<Toolchain> {
+        let (pending, abandoned): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|prefetch| {
+            prefetch.toolchain == *next || candidates.contains(&&prefetch.toolchain)
+        });
+        self.pending = pending;
+        self.abandoned.extend(abandoned);
+
+        for &t in candidates {
+            if !self.started.insert(t.rustup_name()) {
+                continue;
+            }
+            debug!("prefetching {}", t);
+            let (client, dl_params, toolchain) =
+                (self.client.clone(), self.dl_params.clone(), t.clone());
+            let handle = thread::spawn(move || toolchain.install(&client, &dl_params));
+            self.pending.push(Prefetch {
+                toolchain: t.clone(),
+                handle,
+            });
+        }
+
+        let (finished, running): (Vec<_>, Vec<_>) = self
+            .abandoned
+            .drain(..)
+            .partition(|prefetch| prefetch.handle.is_finished());
+        self.abandoned = running;
+        finished.into_iter().filter_map(Prefetch::join).collect()
+    }
+
+    /// Waits for the background installation of `t` to finish, if there is
+    /// one, before it is installed for testing.
+    pub(crate) fn wait(&mut self, t: &Toolchain) {
+        let found = self
+            .pending
+            .iter()
+            .position(|prefetch| prefetch.toolchain == *t);
+        if let Some(index) = found {
+            self.pending.swap_remove(index).join();
+            return;
+        }
+        let found = self
+            .abandoned
+            .iter()
+            .position(|prefetch| prefetch.toolchain == *t);
+        if let Some(index) = found {
+            self.abandoned.swap_remove(index).join();
+        }
+    }
+
+    /// Waits for all background installations to finish once the bisection
+    /// is over, and returns the toolchains which the caller should remove.
+    pub(crate) fn finish(self) -> Vec<Toolchain> {
+        self.pending
+            .into_iter()
+            .chain(self.abandoned)
+            .filter_map(Prefetch::join)
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+//! Reporting the progress of a bisection, which `cargo bisect-rustc` prints to
+//! stderr and other users of the library can handle as they see fit.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use colored::Colorize;
+
+use crate::toolchains::Toolchain;
+
+/// Something that happened during a bisection.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Progress<'a> {
+    /// A line of status, such as the range being searched or the outcome of
+    /// a test. Empty lines separate the steps of a bisection.
+    Message(&'a str),
+    /// A problem which does not stop the bisection, such as a download which
+    /// is retried.
+    Warning(&'a str),
+    /// A toolchain is about to be installed.
+    Installing(&'a Toolchain),
+    /// Part of the output of the test, as it is written, when it is shown
+    /// with `-vv` or `--prompt`.
+    TestOutput(&'a [u8]),
+    /// Part of a download was received, which brings it to `downloaded`
+    /// bytes out of `total` if known.
+    Download {
+        name: &'a str,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+}
+
+/// Receives the progress of a bisection.
+#[derive(Clone)]
+pub struct Reporter {
+    report: Arc<dyn Fn(&Progress<'_>) + Send + Sync>,
+    /// Whether the progress is printed to stderr, where downloads are shown
+    /// as progress bars instead of [`Progress::Download`], and the test may
+    /// write its output directly, so that it stays a terminal.
+    stderr: bool,
+}
+
+impl Reporter {
+    /// Hands the progress to `f`, which may be called from several threads
+    /// at once with `--jobs`.
+    pub fn new(f: impl Fn(&Progress<'_>) + Send + Sync + 'static) -> Reporter {
+        Reporter {
+            report: Arc::new(f),
+            stderr: false,
+        }
+    }
+
+    /// Prints the progress to stderr, like `cargo bisect-rustc` does.
+    #[must_use]
+    pub fn stderr() -> Reporter {
+        Reporter {
+            stderr: true,
+            ..Reporter::new(|progress| match progress {
+                Progress::Message(message) => eprintln!("{message}"),
+                Progress::Warning(warning) => eprintln!("warning: {warning}"),
+                Progress::Installing(t) => eprintln!("installing {}", t.to_string().green()),
+                Progress::TestOutput(output) => {
+                    let _ = io::stderr().write_all(output);
+                }
+                // Shown as progress bars instead.
+                Progress::Download { .. } => {}
+            })
+        }
+    }
+
+    pub(crate) fn report(&self, progress: &Progress<'_>) {
+        (self.report)(progress);
+    }
+
+    pub(crate) fn prints_to_stderr(&self) -> bool {
+        self.stderr
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
+
+/// Reports a line of status, like `eprintln!`.
+macro_rules! status {
+    ($reporter:expr) => {
+        $reporter.report(&$crate::progress::Progress::Message(""))
+    };
+    ($reporter:expr, $($arg:tt)+) => {
+        $reporter.report(&$crate::progress::Progress::Message(&format!($($arg)+)))
+    };
+}
+
+/// Reports a problem which does not stop the bisection.
+macro_rules! warning {
+    ($reporter:expr, $($arg:tt)+) => {
+        $reporter.report(&$crate::progress::Progress::Warning(&format!($($arg)+)))
+    };
+}
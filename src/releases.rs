@@ -0,0 +1,136 @@
+//! Stable releases of Rust, for bisecting over point releases before falling
+//! back to nightlies.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use log::debug;
+use reqwest::blocking::Client;
+
+use crate::mirrors;
+use crate::toolchains::{local_path, DownloadError};
+use crate::Reporter;
+
+/// A stable release version, such as `1.62.1`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version {
+    pub(crate) major: u32,
+    pub(crate) minor: u32,
+    pub(crate) patch: u32,
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts = s.split('.').map(str::parse::<u32>);
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), patch, None) => Ok(Version {
+                major,
+                minor,
+                patch: patch.transpose()?.unwrap_or(0),
+            }),
+            _ => bail!("`{s}` is not a release version like 1.62.0"),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Version {
+    /// Whether this is a patch release, whose changes were backported to
+    /// the release branch instead of landing on master.
+    pub(crate) fn is_point_release(self) -> bool {
+        self.patch > 0
+    }
+}
+
+/// Checks whether `version` was released, by looking for its manifest on the
+/// dist `servers`.
+fn is_released(
+    client: &Client,
+    servers: &[String],
+    version: Version,
+    reporter: &Reporter,
+) -> anyhow::Result<bool> {
+    let urls = mirrors::urls(servers, &format!("/channel-rust-{version}.toml"));
+    let found = mirrors::first_available(&urls, reporter, |url| {
+        debug!("checking for release <{}>...", url);
+        if let Some(path) = local_path(url) {
+            return if path.is_file() {
+                Ok(())
+            } else {
+                Err(DownloadError::NotFound(url.to_string()))
+            };
+        }
+        let response = client.head(url).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(DownloadError::NotFound(url.to_string()));
+        }
+        response.error_for_status()?;
+        Ok(())
+    });
+    match found {
+        Ok(()) => Ok(true),
+        Err(DownloadError::NotFound(_)) => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("failed to look up release {version}")),
+    }
+}
+
+/// Returns all releases from `start` to `end` (inclusive), including patch
+/// releases, in order.
+pub(crate) fn releases_between(
+    client: &Client,
+    servers: &[String],
+    start: Version,
+    end: Version,
+    reporter: &Reporter,
+) -> anyhow::Result<Vec<Version>> {
+    if start.major != end.major {
+        bail!("cannot bisect releases across major versions ({start} to {end})");
+    }
+    status!(reporter, "looking up releases from {start} to {end}");
+    let mut releases = Vec::new();
+    for minor in start.minor..=end.minor {
+        for patch in 0.. {
+            let version = Version {
+                major: start.major,
+                minor,
+                patch,
+            };
+            if version > end || (patch > 0 && !is_released(client, servers, version, reporter)?) {
+                break;
+            }
+            if version >= start {
+                releases.push(version);
+            }
+        }
+    }
+    Ok(releases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version() {
+        let v = |major, minor, patch| Version {
+            major,
+            minor,
+            patch,
+        };
+        assert_eq!("1.62.1".parse::<Version>().unwrap(), v(1, 62, 1));
+        assert_eq!("1.60".parse::<Version>().unwrap(), v(1, 60, 0));
+        assert!("2022-08-01".parse::<Version>().is_err());
+        assert!("1.62.0-beta.1".parse::<Version>().is_err());
+        assert!("1.62.0.1".parse::<Version>().is_err());
+        assert!(v(1, 62, 1) > v(1, 62, 0));
+        assert!(v(1, 62, 0) > v(1, 61, 3));
+    }
+}
@@ -1,9 +1,24 @@
-use anyhow::Context;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-use crate::{Bound, Commit, GitDate, git, github};
+use anyhow::{bail, Context};
 
-pub(crate) trait RustRepositoryAccessor {
+use crate::toolchains::{parse_to_utc_date, YYYY_MM_DD};
+use crate::{Bound, Commit, GitDate, Reporter, git, github};
+
+/// Name of the file listing the commits of the range in a `--prefetch-to`
+/// directory.
+pub(crate) const COMMITS_FILE: &str = "commits.txt";
+
+/// Looks up the commits of rust-lang/rust, to bisect the CI builds between
+/// two nightlies or commits.
+pub trait RustRepositoryAccessor {
     /// Maps `bound` to its associated date, looking up its commit if necessary.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the commit cannot be looked up.
     fn bound_to_date(&self, bound: Bound) -> anyhow::Result<GitDate> {
         match bound {
             Bound::Date(date) => Ok(date),
@@ -13,22 +28,66 @@ pub(crate) trait RustRepositoryAccessor {
 
     /// Looks up commit associated with `commit_ref`, which can be either a sha
     /// or a more general reference like "origin/master".
+    ///
+    /// # Errors
+    ///
+    /// Fails if the reference does not exist or cannot be looked up.
     fn commit(&self, commit_ref: &str) -> anyhow::Result<Commit>;
 
     /// Looks up a series of commits ending with `end_sha`; the resulting series
     /// should start with `start_sha`. If `start_sha` is not a predecessor of
     /// `end_sha` in the history, then the series will cover all commits as far
     /// back as the date associated with `start_sha`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the commits cannot be looked up.
     fn commits(&self, start_sha: &str, end_sha: &str) -> anyhow::Result<Vec<Commit>>;
 }
 
-pub(crate) struct AccessViaLocalGit;
+/// Looks up commits in a clone of rust-lang/rust, which is made in `rust.git`
+/// unless one is given in the `RUST_SRC_REPO` environment variable.
+pub struct AccessViaLocalGit {
+    reporter: Reporter,
+}
+
+/// Looks up commits with the GitHub API, authenticated with the
+/// `GITHUB_TOKEN` environment variable if it is set.
+pub struct AccessViaGithub {
+    reporter: Reporter,
+}
+
+/// Reads the commits from a file written by `--prefetch-to`, for bisecting
+/// without network access.
+pub struct AccessViaCommitsFile {
+    path: PathBuf,
+    reporter: Reporter,
+}
+
+impl AccessViaLocalGit {
+    #[must_use]
+    pub fn new(reporter: Reporter) -> Self {
+        AccessViaLocalGit { reporter }
+    }
+}
+
+impl AccessViaGithub {
+    #[must_use]
+    pub fn new(reporter: Reporter) -> Self {
+        AccessViaGithub { reporter }
+    }
+}
 
-pub(crate) struct AccessViaGithub;
+impl AccessViaCommitsFile {
+    #[must_use]
+    pub fn new(path: PathBuf, reporter: Reporter) -> Self {
+        AccessViaCommitsFile { path, reporter }
+    }
+}
 
 impl RustRepositoryAccessor for AccessViaLocalGit {
     fn commit(&self, commit_ref: &str) -> anyhow::Result<Commit> {
-        git::get_commit(commit_ref)
+        git::get_commit(commit_ref, &self.reporter)
     }
     fn commits(&self, start_sha: &str, end_sha: &str) -> anyhow::Result<Vec<Commit>> {
         let end_sha = if end_sha == "origin/master" {
@@ -36,18 +95,20 @@ impl RustRepositoryAccessor for AccessViaLocalGit {
         } else {
             end_sha
         };
-        eprintln!(
+        status!(
+            self.reporter,
             "fetching (via local git) commits from {} to {}",
-            start_sha, end_sha
+            start_sha,
+            end_sha
         );
-        git::get_commits_between(start_sha, end_sha)
+        git::get_commits_between(start_sha, end_sha, &self.reporter)
             .context("failed during attempt to create/access local git repository")
     }
 }
 
 impl RustRepositoryAccessor for AccessViaGithub {
     fn commit(&self, commit_ref: &str) -> anyhow::Result<Commit> {
-        github::get_commit(commit_ref)
+        github::get_commit(commit_ref, &self.reporter)
     }
 
     fn commits(&self, start_sha: &str, end_sha: &str) -> anyhow::Result<Vec<Commit>> {
@@ -61,7 +122,8 @@ impl RustRepositoryAccessor for AccessViaGithub {
             .bound_to_date(Bound::Commit(start_sha.to_string()))?
             .pred();
 
-        eprintln!(
+        status!(
+            self.reporter,
             "fetching (via remote github) commits from max({}, {}) to {}",
             start_sha,
             since_date.format(crate::YYYY_MM_DD),
@@ -74,6 +136,115 @@ impl RustRepositoryAccessor for AccessViaGithub {
             most_recent_sha: end_sha,
         };
 
-        query.get_commits()
+        query.get_commits(&self.reporter)
+    }
+}
+
+impl AccessViaCommitsFile {
+    fn read(&self) -> anyhow::Result<Vec<Commit>> {
+        let path = self.path.display();
+        let commits = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read commits from {path}"))?;
+        commits
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let mut parts = line.splitn(3, ' ');
+                match (parts.next(), parts.next().map(parse_to_utc_date)) {
+                    (Some(sha), Some(Ok(date))) => Ok(Commit {
+                        sha: sha.to_string(),
+                        date,
+                        summary: parts.next().unwrap_or_default().to_string(),
+                    }),
+                    _ => bail!("{path}:{}: expected `SHA YYYY-MM-DD SUMMARY`", i + 1),
+                }
+            })
+            .collect()
+    }
+
+    fn position(&self, commits: &[Commit], commit_ref: &str) -> anyhow::Result<usize> {
+        if commit_ref == "origin/master" && !commits.is_empty() {
+            return Ok(commits.len() - 1);
+        }
+        commits
+            .iter()
+            .position(|commit| commit.sha.starts_with(commit_ref))
+            .with_context(|| format!("{commit_ref} is not in {}", self.path.display()))
+    }
+}
+
+impl RustRepositoryAccessor for AccessViaCommitsFile {
+    fn commit(&self, commit_ref: &str) -> anyhow::Result<Commit> {
+        let mut commits = self.read()?;
+        let i = self.position(&commits, commit_ref)?;
+        Ok(commits.swap_remove(i))
+    }
+
+    fn commits(&self, start_sha: &str, end_sha: &str) -> anyhow::Result<Vec<Commit>> {
+        status!(
+            self.reporter,
+            "reading commits from {} to {} in {}",
+            start_sha,
+            end_sha,
+            self.path.display()
+        );
+        let commits = self.read()?;
+        let start = self.position(&commits, start_sha)?;
+        let end = self.position(&commits, end_sha)?;
+        if start > end {
+            bail!(
+                "{start_sha} comes after {end_sha} in {}",
+                self.path.display()
+            );
+        }
+        Ok(commits[start..=end].to_vec())
+    }
+}
+
+/// Writes `commits` to be read back by `AccessViaCommitsFile`, one per line
+/// as `SHA YYYY-MM-DD SUMMARY`, oldest first.
+pub(crate) fn write_commits(path: &Path, commits: &[Commit]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    for commit in commits {
+        writeln!(
+            file,
+            "{} {} {}",
+            commit.sha,
+            commit.date.format(YYYY_MM_DD),
+            commit.summary.lines().next().unwrap_or_default()
+        )?;
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_commits_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(COMMITS_FILE);
+        let commit = |sha: &str, date: &str, summary: &str| Commit {
+            sha: sha.to_string(),
+            date: parse_to_utc_date(date).unwrap(),
+            summary: summary.to_string(),
+        };
+        let commits = vec![
+            commit("aaaa", "2022-08-01", "Auto merge of #1"),
+            commit("bbbb", "2022-08-01", "Auto merge of #2\n\nDetails"),
+            commit("cccc", "2022-08-02", ""),
+        ];
+        write_commits(&path, &commits).unwrap();
+
+        let access = AccessViaCommitsFile::new(path, Reporter::new(|_| {}));
+        assert_eq!(access.commit("bb").unwrap().summary, "Auto merge of #2");
+        assert_eq!(access.commit("origin/master").unwrap(), commits[2]);
+        assert!(access.commit("dddd").is_err());
+
+        let found = access.commits("bbbb", "cccc").unwrap();
+        let shas: Vec<&str> = found.iter().map(|c| c.sha.as_str()).collect();
+        assert_eq!(shas, ["bbbb", "cccc"]);
+        assert!(access.commits("cccc", "aaaa").is_err());
     }
 }
@@ -0,0 +1,276 @@
+//! Machine-readable report of a bisection, for tools which would otherwise
+//! have to scrape the human-readable output.
+
+use std::fs;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::least_satisfying::{Satisfies, Trials};
+use crate::toolchains::{TestOutcome, TestRun, Toolchain, YYYY_MM_DD};
+use crate::{searched_range, BisectionResult, Commit, Config, RegressOn};
+
+/// A single toolchain tested during a bisection phase.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct Step {
+    toolchain: String,
+    verdict: Satisfies,
+    /// Exit code of the test command, if it ran and was not killed by a signal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_status: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    install_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trials: Option<Trials>,
+    /// Measurements of `--perf-metric` with `--regress=slower-than`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    measurements: Option<Vec<f64>>,
+    /// Peak resident set size of the test command in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak_rss_bytes: Option<u64>,
+    /// Whether the test command was killed for exceeding `--timeout`.
+    timed_out: bool,
+    /// Why the toolchain could not be tested, for `Satisfies::Unknown`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Why the toolchain was excluded from the bisection without testing it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+    /// Whether the verdict was taken from the session journal instead of
+    /// running the test.
+    from_session: bool,
+}
+
+impl Step {
+    pub(crate) fn tested(
+        t: &Toolchain,
+        verdict: Satisfies,
+        install: Duration,
+        run: &TestRun,
+    ) -> Self {
+        Step {
+            exit_status: run.status,
+            install_secs: Some(install.as_secs_f64()),
+            test_secs: Some(run.duration.as_secs_f64()),
+            measurements: run.measurements.clone(),
+            peak_rss_bytes: run.peak_rss,
+            timed_out: run.outcome == TestOutcome::TimedOut,
+            ..Step::new(t, verdict)
+        }
+    }
+
+    pub(crate) fn tested_trials(
+        t: &Toolchain,
+        trials: Trials,
+        install: Duration,
+        test: Duration,
+    ) -> Self {
+        Step {
+            install_secs: Some(install.as_secs_f64()),
+            test_secs: Some(test.as_secs_f64()),
+            trials: Some(trials),
+            ..Step::new(t, trials.satisfies())
+        }
+    }
+
+    pub(crate) fn failed(t: &Toolchain, error: &dyn std::error::Error) -> Self {
+        Step {
+            error: Some(error.to_string()),
+            ..Step::new(t, Satisfies::Unknown)
+        }
+    }
+
+    pub(crate) fn skipped_missing(t: &Toolchain, missing: &[String]) -> Self {
+        Step {
+            skipped: Some(format!("component missing: {}", missing.join(", "))),
+            ..Step::new(t, Satisfies::Unknown)
+        }
+    }
+
+    pub(crate) fn skipped_by_user(t: &Toolchain) -> Self {
+        Step {
+            skipped: Some("skipped by user".to_string()),
+            ..Step::new(t, Satisfies::Unknown)
+        }
+    }
+
+    pub(crate) fn from_session(t: &Toolchain, verdict: Satisfies, trials: Option<Trials>) -> Self {
+        Step {
+            trials,
+            from_session: true,
+            ..Step::new(t, verdict)
+        }
+    }
+
+    fn new(t: &Toolchain, verdict: Satisfies) -> Self {
+        Step {
+            toolchain: t.to_string(),
+            verdict,
+            exit_status: None,
+            install_secs: None,
+            test_secs: None,
+            trials: None,
+            measurements: None,
+            peak_rss_bytes: None,
+            timed_out: false,
+            error: None,
+            skipped: None,
+            from_session: false,
+        }
+    }
+}
+
+/// The phases of a bisection which completed, each along with whether the
+/// regression was found in it.
+#[derive(Default)]
+pub(crate) struct Phases {
+    pub(crate) release: Option<(BisectionResult, bool)>,
+    pub(crate) nightly: Option<(BisectionResult, bool)>,
+    pub(crate) ci: Option<(BisectionResult, bool)>,
+}
+
+/// How the bisection ended.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Status {
+    /// The last phase found the regression.
+    Found,
+    /// The last phase did not find the regression within its range.
+    NotFound,
+    /// The bisection failed, e.g. because the start of the range already
+    /// has the regression.
+    Error,
+}
+
+#[derive(Serialize)]
+struct Report {
+    version: &'static str,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    host: String,
+    target: String,
+    /// What the `measurements` of the steps are, with `--regress=slower-than`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metric: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release: Option<PhaseReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nightly: Option<PhaseReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ci: Option<PhaseReport>,
+}
+
+#[derive(Serialize)]
+struct PhaseReport {
+    start: String,
+    end: String,
+    searched: Vec<String>,
+    steps: Vec<Step>,
+    /// Index into `searched` of the first regressed toolchain.
+    found: usize,
+    regressed: String,
+    /// Whether the regression was confirmed to be within the searched range.
+    regression_found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    regressed_commit: Option<CommitReport>,
+}
+
+#[derive(Serialize)]
+struct CommitReport {
+    sha: String,
+    date: String,
+    summary: String,
+    url: String,
+}
+
+impl PhaseReport {
+    fn new(cfg: &Config, result: &BisectionResult, regression_found: bool) -> Self {
+        let (start, end) = searched_range(cfg, &result.searched);
+        let (commit_range, regressed_commit) = match (result.commits.first(), result.commits.last())
+        {
+            (Some(first), Some(last)) => {
+                let commit = &result.commits[result.found];
+                (
+                    Some(format!(
+                        "https://github.com/rust-lang/rust/compare/{}...{}",
+                        first.sha, last.sha
+                    )),
+                    Some(CommitReport::new(commit)),
+                )
+            }
+            _ => (None, None),
+        };
+        PhaseReport {
+            start: start.to_string(),
+            end: end.to_string(),
+            searched: result.searched.iter().map(ToString::to_string).collect(),
+            steps: result.steps.clone(),
+            found: result.found,
+            regressed: result.searched[result.found].to_string(),
+            regression_found,
+            confidence: result.confidence,
+            commit_range,
+            regressed_commit,
+        }
+    }
+}
+
+impl CommitReport {
+    fn new(commit: &Commit) -> Self {
+        CommitReport {
+            sha: commit.sha.clone(),
+            date: commit.date.format(YYYY_MM_DD).to_string(),
+            summary: commit.summary.clone(),
+            url: format!("https://github.com/rust-lang/rust/commit/{}", commit.sha),
+        }
+    }
+}
+
+/// Writes the JSON report to `--report-file`, or to stdout if it was not given,
+/// with the `error` that stopped the bisection if any.
+pub(crate) fn write_json(
+    cfg: &Config,
+    phases: &Phases,
+    error: Option<&anyhow::Error>,
+) -> anyhow::Result<()> {
+    let last = [&phases.ci, &phases.nightly, &phases.release]
+        .into_iter()
+        .find_map(Option::as_ref);
+    let status = match (error, last) {
+        (Some(_), _) => Status::Error,
+        (None, Some((_, true))) => Status::Found,
+        (None, _) => Status::NotFound,
+    };
+    let phase = |phase: &Option<(BisectionResult, bool)>| {
+        phase
+            .as_ref()
+            .map(|(result, found)| PhaseReport::new(cfg, result, *found))
+    };
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        status,
+        error: error.map(|e| format!("{e:#}")),
+        host: cfg.args.host.clone(),
+        target: cfg.target.clone(),
+        metric: (cfg.args.regress == RegressOn::SlowerThan).then(|| cfg.args.perf_metric.name()),
+        release: phase(&phases.release),
+        nightly: phase(&phases.nightly),
+        ci: phase(&phases.ci),
+    };
+    let mut json = serde_json::to_string_pretty(&report)?;
+    json.push('\n');
+    match cfg.args.report_file {
+        Some(ref path) => fs::write(path, json)
+            .with_context(|| format!("failed to write report to {}", path.display())),
+        None => Ok(io::stdout().write_all(json.as_bytes())?),
+    }
+}
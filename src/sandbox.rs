@@ -0,0 +1,444 @@
+//! Sandbox for the test command, which runs arbitrary code from bug reports.
+//!
+//! On Unix, the test command runs with resource limits and a private temporary
+//! directory. On Linux, it also runs in its own user, mount and network
+//! namespaces where they are available: it has no network access, and the
+//! filesystem is read-only except for the test directory, the temporary
+//! directory, cargo's home and the directories given with
+//! `--sandbox-writable`.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::Reporter;
+
+/// Resource limits of the test command, applied to each of its processes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits {
+    /// CPU time in seconds.
+    pub(crate) cpu_secs: Option<u64>,
+    /// Address space in bytes.
+    pub(crate) memory: Option<u64>,
+    /// Size of the files it writes, in bytes.
+    pub(crate) file_size: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Sandbox {
+    limits: Limits,
+    network: bool,
+    writable: Vec<PathBuf>,
+    /// Whether namespaces are available to isolate the test.
+    namespaces: bool,
+}
+
+impl Sandbox {
+    /// Sets up a sandbox, warning about the isolation that is unavailable on
+    /// this host.
+    pub(crate) fn new(
+        limits: Limits,
+        network: bool,
+        writable: &[PathBuf],
+        reporter: &Reporter,
+    ) -> io::Result<Sandbox> {
+        let writable = writable
+            .iter()
+            .map(|dir| dir.canonicalize())
+            .collect::<io::Result<_>>()?;
+        let mut sandbox = Sandbox {
+            limits,
+            network,
+            writable,
+            namespaces: false,
+        };
+        sandbox.namespaces = sandbox.probe_namespaces();
+        if !sandbox.namespaces {
+            warning!(
+                reporter,
+                "namespaces are unavailable, so the sandboxed test keeps access \
+                 to the network and can write anywhere it has permission to"
+            );
+        }
+        Ok(sandbox)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn probe_namespaces(&self) -> bool {
+        match linux::Namespaces::new(self, &[]) {
+            Ok(namespaces) => {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.args(["-c", "exit 0"]);
+                // SAFETY: `Namespaces::enter` only makes system calls.
+                unsafe {
+                    std::os::unix::process::CommandExt::pre_exec(&mut cmd, move || {
+                        namespaces.enter()
+                    });
+                }
+                matches!(cmd.status(), Ok(status) if status.success())
+            }
+            Err(_) => false,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn probe_namespaces(&self) -> bool {
+        false
+    }
+
+    /// Makes `cmd` run in the sandbox, with `tmp` as its temporary directory,
+    /// and `test_dir` as well as `tmp` writable.
+    pub(crate) fn apply(&self, cmd: &mut Command, test_dir: &Path, tmp: &Path) -> io::Result<()> {
+        for var in ["TMPDIR", "TMP", "TEMP"] {
+            cmd.env(var, tmp);
+        }
+
+        #[cfg(unix)]
+        {
+            let limits = self.limits.clone();
+            #[cfg(target_os = "linux")]
+            let namespaces = if self.namespaces {
+                let mut namespaces =
+                    linux::Namespaces::new(self, &[test_dir.canonicalize()?, tmp.canonicalize()?])?;
+                let cwd = std::env::current_dir()?;
+                namespaces.set_cwd(&match cmd.get_current_dir() {
+                    Some(dir) => cwd.join(dir),
+                    None => cwd,
+                })?;
+                Some(namespaces)
+            } else {
+                None
+            };
+            // SAFETY: the closure only makes system calls, without allocating.
+            unsafe {
+                std::os::unix::process::CommandExt::pre_exec(cmd, move || {
+                    #[cfg(target_os = "linux")]
+                    if let Some(namespaces) = &namespaces {
+                        namespaces.enter()?;
+                    }
+                    unix::set_limits(&limits)
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = test_dir;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io;
+
+    use super::Limits;
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    type Resource = libc::c_int;
+
+    pub(super) fn set_limits(limits: &Limits) -> io::Result<()> {
+        let resources = [
+            (libc::RLIMIT_CPU, limits.cpu_secs),
+            (libc::RLIMIT_AS, limits.memory),
+            (libc::RLIMIT_FSIZE, limits.file_size),
+        ];
+        for (resource, limit) in resources {
+            if let Some(limit) = limit {
+                set_limit(resource, limit)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_limit(resource: Resource, limit: u64) -> io::Result<()> {
+        let rlimit = libc::rlimit {
+            rlim_cur: limit,
+            rlim_max: limit,
+        };
+        // SAFETY: `rlimit` is a valid pointer for the duration of the call.
+        if unsafe { libc::setrlimit(resource, std::ptr::addr_of!(rlimit)) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use super::Sandbox;
+
+    const ROOT: &[u8] = b"/\0";
+
+    /// Everything needed to enter the namespaces of the sandbox, prepared
+    /// ahead of time since nothing may be allocated after forking.
+    pub(super) struct Namespaces {
+        flags: libc::c_int,
+        uid_map: CString,
+        gid_map: CString,
+        writable: Vec<CString>,
+        /// Mount points to make read-only, along with the flags they must keep.
+        read_only: Vec<(CString, libc::c_ulong)>,
+        /// Working directory of the command, which is entered again once the
+        /// writable directories are mounted over.
+        cwd: Option<CString>,
+    }
+
+    impl Namespaces {
+        pub(super) fn new(sandbox: &Sandbox, writable: &[PathBuf]) -> io::Result<Namespaces> {
+            let mut flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS;
+            if !sandbox.network {
+                flags |= libc::CLONE_NEWNET;
+            }
+            // SAFETY: these calls cannot fail.
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let writable: Vec<PathBuf> =
+                writable.iter().chain(&sandbox.writable).cloned().collect();
+            let read_only = if writable.is_empty() {
+                Vec::new()
+            } else {
+                read_only_mounts(&fs::read_to_string("/proc/self/mountinfo")?, &writable)
+                    .into_iter()
+                    .map(|(path, flags)| Ok((c_path(&path)?, flags)))
+                    .collect::<io::Result<_>>()?
+            };
+            Ok(Namespaces {
+                flags,
+                // Keep our own ids inside the namespace.
+                uid_map: CString::new(format!("{uid} {uid} 1")).unwrap(),
+                gid_map: CString::new(format!("{gid} {gid} 1")).unwrap(),
+                writable: writable
+                    .iter()
+                    .map(|dir| c_path(dir))
+                    .collect::<io::Result<_>>()?,
+                read_only,
+                cwd: None,
+            })
+        }
+
+        pub(super) fn set_cwd(&mut self, cwd: &Path) -> io::Result<()> {
+            self.cwd = Some(c_path(&cwd.canonicalize()?)?);
+            Ok(())
+        }
+
+        /// Moves the current process into new namespaces. This runs between
+        /// fork and exec.
+        pub(super) fn enter(&self) -> io::Result<()> {
+            // SAFETY: `unshare` only takes flags.
+            check(unsafe { libc::unshare(self.flags) })?;
+            write(b"/proc/self/setgroups\0", b"deny")?;
+            write(b"/proc/self/uid_map\0", self.uid_map.as_bytes())?;
+            write(b"/proc/self/gid_map\0", self.gid_map.as_bytes())?;
+
+            // Don't let the mounts below propagate to the host.
+            let root = ROOT.as_ptr().cast();
+            // SAFETY: `root` is a nul-terminated string, and the other
+            // pointers may be null when changing the propagation of a mount.
+            check(unsafe {
+                libc::mount(
+                    ptr::null(),
+                    root,
+                    ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    ptr::null(),
+                )
+            })?;
+            // Bind mounts of the writable directories stay writable when the
+            // mounts they come from are made read-only.
+            for dir in &self.writable {
+                // SAFETY: `dir` is a nul-terminated string which outlives the
+                // call, and the other pointers may be null for a bind mount.
+                check(unsafe {
+                    libc::mount(
+                        dir.as_ptr(),
+                        dir.as_ptr(),
+                        ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        ptr::null(),
+                    )
+                })?;
+            }
+            for (mount_point, flags) in &self.read_only {
+                // Some mounts, such as those locked by a parent namespace,
+                // cannot be remounted, which is fine.
+                // SAFETY: `mount_point` is a nul-terminated string which
+                // outlives the call, and the other pointers may be null when
+                // remounting.
+                unsafe {
+                    libc::mount(
+                        ptr::null(),
+                        mount_point.as_ptr(),
+                        ptr::null(),
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | flags,
+                        ptr::null(),
+                    );
+                }
+            }
+            if let Some(cwd) = &self.cwd {
+                // SAFETY: `cwd` is a nul-terminated string.
+                check(unsafe { libc::chdir(cwd.as_ptr()) })?;
+            }
+            Ok(())
+        }
+    }
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    fn check(ret: libc::c_int) -> io::Result<()> {
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Writes `contents` to the file at the nul-terminated `path`, without
+    /// allocating, since this runs between fork and exec.
+    fn write(path: &[u8], contents: &[u8]) -> io::Result<()> {
+        // SAFETY: `path` is nul-terminated.
+        let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_WRONLY) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `contents` is valid for `contents.len()` bytes, and `fd` was
+        // opened above and is closed only once.
+        let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+        // SAFETY: `fd` is open, and not used afterwards.
+        unsafe { libc::close(fd) };
+        if usize::try_from(written).ok() == Some(contents.len()) {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Parses `/proc/self/mountinfo` for the mount points to make read-only,
+    /// along with the flags which must be kept when remounting them.
+    ///
+    /// Pseudo filesystems and mounts within writable directories are left
+    /// alone.
+    pub(super) fn read_only_mounts(
+        mountinfo: &str,
+        writable: &[PathBuf],
+    ) -> Vec<(PathBuf, libc::c_ulong)> {
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ').skip(4);
+                let mount_point = PathBuf::from(unescape(fields.next()?));
+                let options = fields.next()?;
+                if mount_point.starts_with("/proc")
+                    || mount_point.starts_with("/dev")
+                    || writable.iter().any(|dir| mount_point.starts_with(dir))
+                {
+                    return None;
+                }
+                let flags = options
+                    .split(',')
+                    .map(|option| match option {
+                        "nosuid" => libc::MS_NOSUID,
+                        "nodev" => libc::MS_NODEV,
+                        "noexec" => libc::MS_NOEXEC,
+                        "noatime" => libc::MS_NOATIME,
+                        "nodiratime" => libc::MS_NODIRATIME,
+                        "relatime" => libc::MS_RELATIME,
+                        _ => 0,
+                    })
+                    .fold(0, |flags, flag| flags | flag);
+                Some((mount_point, flags))
+            })
+            .collect()
+    }
+
+    /// Mount points in mountinfo have spaces and such escaped as `\040`.
+    fn unescape(s: &str) -> String {
+        let bytes = s.as_bytes();
+        let mut unescaped = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+            match octal {
+                Some(byte) if bytes[i] == b'\\' => {
+                    unescaped.push(byte);
+                    i += 4;
+                }
+                _ => {
+                    unescaped.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&unescaped).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn parse_mountinfo() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 0:5 / /proc rw,nosuid,nodev,noexec,relatime shared:2 - proc proc rw
+24 22 0:20 / /tmp rw,nosuid,nodev shared:3 - tmpfs tmpfs rw
+25 22 8:2 / /home/my\\040disk rw,noatime shared:4 - ext4 /dev/sda2 rw
+26 22 8:3 / /work/cache rw,relatime shared:5 - ext4 /dev/sda3 rw
+";
+        let mounts = linux::read_only_mounts(mountinfo, &[PathBuf::from("/work")]);
+        assert_eq!(
+            mounts,
+            [
+                (PathBuf::from("/"), libc::MS_RELATIME),
+                (PathBuf::from("/tmp"), libc::MS_NOSUID | libc::MS_NODEV),
+                (PathBuf::from("/home/my disk"), libc::MS_NOATIME),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn sandboxed_command() {
+        let test_dir = tempfile::tempdir().unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let limits = Limits {
+            cpu_secs: Some(30),
+            ..Limits::default()
+        };
+        let sandbox = Sandbox::new(limits, false, &[], &Reporter::new(|_| {})).unwrap();
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            r#"test "$(ulimit -t)" = 30 && touch "$TMPDIR/a" ./b || exit 1
+            ! touch "$OUTSIDE/escaped" 2>/dev/null || exit 2
+            ! grep -v -e lo: -e '|' /proc/net/dev || exit 3"#,
+        ])
+        .env("OUTSIDE", outside.path())
+        .current_dir(test_dir.path());
+        sandbox
+            .apply(&mut cmd, test_dir.path(), tmp.path())
+            .unwrap();
+        let status = cmd.status().unwrap();
+        if sandbox.namespaces {
+            assert!(status.success(), "{status}");
+        } else {
+            // Without namespaces, nothing prevents writing outside.
+            assert_eq!(status.code(), Some(2));
+        }
+    }
+}
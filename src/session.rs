@@ -0,0 +1,275 @@
+//! On-disk journal of a bisection session, so that an interrupted run can be
+//! resumed without re-testing toolchains whose verdict is already known.
+//!
+//! The journal is a file of JSON lines. The first entry records the command
+//! line that started the session, and further entries are appended as the
+//! bisection progresses. The journal is removed once the bisection completes.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::Utc;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::least_satisfying::{Satisfies, Trials};
+use crate::toolchains::Toolchain;
+use crate::Reporter;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum Entry {
+    /// The command line arguments that started the session.
+    Start { args: Vec<String> },
+    /// The toolchains computed for one bisection phase.
+    Toolchains { toolchains: Vec<String> },
+    /// The verdict of testing a single toolchain.
+    Verdict {
+        toolchain: String,
+        satisfies: Satisfies,
+    },
+    /// The outcome of one batch of repeated tests of a single toolchain.
+    Trials { toolchain: String, trials: Trials },
+}
+
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: File,
+    verdicts: HashMap<String, Satisfies>,
+    /// Batches of trials recorded by a previous run which were not replayed yet.
+    trials: HashMap<String, VecDeque<Trials>>,
+    /// Toolchain lists recorded by a previous run, in phase order.
+    recorded_toolchains: Vec<Vec<String>>,
+    /// Number of toolchain lists recorded by the current run.
+    phase: usize,
+    reporter: Reporter,
+}
+
+impl Journal {
+    /// Starts a new journal in `dir`, recording `args` as the command line
+    /// which started the session.
+    pub(crate) fn create(
+        dir: &Path,
+        args: Vec<String>,
+        reporter: &Reporter,
+    ) -> anyhow::Result<Journal> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create session directory {}", dir.display()))?;
+        // The random suffix tells apart sessions started in the same second.
+        let (file, path) = tempfile::Builder::new()
+            .prefix(&format!("{}-", Utc::now().format("%Y-%m-%dT%H-%M-%S")))
+            .suffix(".jsonl")
+            .tempfile_in(dir)
+            .and_then(|file| file.keep().map_err(|e| e.error))
+            .with_context(|| format!("failed to create session journal in {}", dir.display()))?;
+        let mut journal = Journal {
+            path,
+            file,
+            verdicts: HashMap::new(),
+            trials: HashMap::new(),
+            recorded_toolchains: Vec::new(),
+            phase: 0,
+            reporter: reporter.clone(),
+        };
+        journal.append(&Entry::Start { args })?;
+        Ok(journal)
+    }
+
+    /// Reopens the journal at `path`, returning it along with the command line
+    /// arguments that started the session.
+    pub(crate) fn resume(
+        path: &Path,
+        reporter: &Reporter,
+    ) -> anyhow::Result<(Journal, Vec<String>)> {
+        let reader = BufReader::new(
+            File::open(path)
+                .with_context(|| format!("failed to open session journal {}", path.display()))?,
+        );
+        let mut args = None;
+        let mut verdicts = HashMap::new();
+        let mut trials: HashMap<String, VecDeque<Trials>> = HashMap::new();
+        let mut recorded_toolchains = Vec::new();
+        for (lineno, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                // The last line may be truncated if the previous run was killed
+                // while writing it.
+                Err(e) => {
+                    debug!("ignoring line {} of {}: {e}", lineno + 1, path.display());
+                    continue;
+                }
+            };
+            match entry {
+                Entry::Start { args: a } => args = Some(a),
+                Entry::Toolchains { toolchains } => recorded_toolchains.push(toolchains),
+                Entry::Verdict {
+                    toolchain,
+                    satisfies,
+                } => {
+                    verdicts.insert(toolchain, satisfies);
+                }
+                Entry::Trials {
+                    toolchain,
+                    trials: t,
+                } => trials.entry(toolchain).or_default().push_back(t),
+            }
+        }
+        let args = args.with_context(|| format!("{} is not a session journal", path.display()))?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        status!(
+            reporter,
+            "resuming session {} with {} recorded verdicts",
+            path.display(),
+            verdicts.len()
+        );
+        let journal = Journal {
+            path: path.to_path_buf(),
+            file,
+            verdicts,
+            trials,
+            recorded_toolchains,
+            phase: 0,
+            reporter: reporter.clone(),
+        };
+        Ok((journal, args))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the journal of a completed session. Failing to do so is only
+    /// reported as a warning.
+    pub(crate) fn remove(self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warning!(
+                self.reporter,
+                "failed to remove session journal {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+
+    /// Returns the verdict recorded for `t`, if it was already tested.
+    pub(crate) fn verdict(&self, t: &Toolchain) -> Option<Satisfies> {
+        self.verdicts.get(&t.rustup_name()).copied()
+    }
+
+    pub(crate) fn record_verdict(
+        &mut self,
+        t: &Toolchain,
+        satisfies: Satisfies,
+    ) -> anyhow::Result<()> {
+        let toolchain = t.rustup_name();
+        self.verdicts.insert(toolchain.clone(), satisfies);
+        self.append(&Entry::Verdict {
+            toolchain,
+            satisfies,
+        })
+    }
+
+    /// Returns the next batch of trials recorded for `t` by a previous run.
+    ///
+    /// A probabilistic bisection may test the same toolchain several times, so
+    /// batches are replayed in the order they were recorded.
+    pub(crate) fn take_trials(&mut self, t: &Toolchain) -> Option<Trials> {
+        self.trials.get_mut(&t.rustup_name())?.pop_front()
+    }
+
+    pub(crate) fn record_trials(&mut self, t: &Toolchain, trials: Trials) -> anyhow::Result<()> {
+        self.append(&Entry::Trials {
+            toolchain: t.rustup_name(),
+            trials,
+        })
+    }
+
+    /// Records the toolchains to be searched in the next bisection phase.
+    pub(crate) fn record_toolchains(&mut self, toolchains: &[Toolchain]) -> anyhow::Result<()> {
+        let toolchains: Vec<String> = toolchains.iter().map(Toolchain::rustup_name).collect();
+        let phase = self.phase;
+        self.phase += 1;
+        match self.recorded_toolchains.get(phase) {
+            Some(recorded) if *recorded == toolchains => Ok(()),
+            Some(_) => {
+                warning!(
+                    self.reporter,
+                    "the toolchains to search differ from the ones recorded in {}; \
+                     only verdicts of matching toolchains are reused",
+                    self.path.display()
+                );
+                self.append(&Entry::Toolchains { toolchains })
+            }
+            None => self.append(&Entry::Toolchains { toolchains }),
+        }
+    }
+
+    fn append(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("failed to write session journal {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toolchains::{parse_to_utc_date, ToolchainSpec};
+
+    fn nightly(date: &str) -> Toolchain {
+        Toolchain {
+            spec: ToolchainSpec::Nightly {
+                date: parse_to_utc_date(date).unwrap(),
+            },
+            host: "x86_64-unknown-linux-gnu".to_string(),
+            std_targets: vec!["x86_64-unknown-linux-gnu".to_string()],
+        }
+    }
+
+    #[test]
+    fn resume_restores_args_and_verdicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = vec![
+            "cargo-bisect-rustc".to_string(),
+            "--start=2022-01-01".to_string(),
+        ];
+        let (good, bad, untested) = (
+            nightly("2022-01-01"),
+            nightly("2022-01-02"),
+            nightly("2022-01-03"),
+        );
+
+        let path = {
+            let mut journal =
+                Journal::create(dir.path(), args.clone(), &Reporter::new(|_| {})).unwrap();
+            journal
+                .record_toolchains(&[good.clone(), bad.clone(), untested.clone()])
+                .unwrap();
+            journal.record_verdict(&good, Satisfies::No).unwrap();
+            journal.record_verdict(&bad, Satisfies::Yes).unwrap();
+            journal.path().to_path_buf()
+        };
+
+        let (journal, resumed_args) = Journal::resume(&path, &Reporter::new(|_| {})).unwrap();
+        assert_eq!(resumed_args, args);
+        assert_eq!(journal.verdict(&good), Some(Satisfies::No));
+        assert_eq!(journal.verdict(&bad), Some(Satisfies::Yes));
+        assert_eq!(journal.verdict(&untested), None);
+        assert_eq!(journal.recorded_toolchains.len(), 1);
+
+        // Another session started in the same second gets its own journal.
+        let other = Journal::create(dir.path(), args, &Reporter::new(|_| {})).unwrap();
+        assert_ne!(other.path(), path);
+        journal.remove();
+        assert!(!path.exists());
+    }
+}
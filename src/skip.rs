@@ -0,0 +1,323 @@
+//! Toolchains left out of a bisection with `--skip` and `--skip-file`, such as
+//! nightlies which are known to be broken for reasons unrelated to the
+//! regression.
+
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+
+use crate::toolchains::{parse_to_utc_date, Toolchain, ToolchainSpec};
+use crate::{Commit, GitDate};
+
+/// A nightly or commit to leave out, or an inclusive range of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Skip {
+    /// The nightly of a day, or the commits of that day.
+    Date(GitDate),
+    /// A commit, possibly abbreviated.
+    Commit(String),
+    Dates(GitDate, GitDate),
+    Commits(String, String),
+}
+
+impl FromStr for Skip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Skip> {
+        let s = s.trim();
+        if s.is_empty() {
+            bail!("expected a date, a commit or a range START..END");
+        }
+        let (start, end) = match s.split_once("..") {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => {
+                return Ok(match parse_to_utc_date(s) {
+                    Ok(date) => Skip::Date(date),
+                    Err(_) => Skip::Commit(s.to_string()),
+                })
+            }
+        };
+        match (parse_to_utc_date(start), parse_to_utc_date(end)) {
+            (Ok(start), Ok(end)) if start <= end => Ok(Skip::Dates(start, end)),
+            (Ok(_), Ok(_)) => bail!("the range {s} ends before it starts"),
+            (Err(_), Err(_)) if !start.is_empty() && !end.is_empty() => {
+                Ok(Skip::Commits(start.to_string(), end.to_string()))
+            }
+            _ => bail!("the range {s} must be between two dates or two commits"),
+        }
+    }
+}
+
+/// Reads a skip file, which has one `--skip` value per line. Empty lines and
+/// lines starting with `#` are ignored.
+pub(crate) fn read_file(path: &Path) -> anyhow::Result<Vec<Skip>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(i, line)| {
+            line.parse()
+                .with_context(|| format!("{}:{}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Returns which of `toolchains` are left out by `skips`. When bisecting CI
+/// builds, `commits` are those of the toolchains, so that a commit can be
+/// skipped by its date, and `date_of` looks up the date of a commit outside
+/// of the bisection.
+pub(crate) fn skipped(
+    skips: &[Skip],
+    toolchains: &[Toolchain],
+    commits: &[Commit],
+    date_of: &dyn Fn(&str) -> Option<GitDate>,
+) -> Vec<bool> {
+    let mut skipped = vec![false; toolchains.len()];
+    for skip in skips {
+        match skip {
+            Skip::Date(date) => mark(&mut skipped, toolchains, commits, |d, _| d == *date),
+            Skip::Dates(start, end) => mark(&mut skipped, toolchains, commits, |d, _| {
+                *start <= d && d <= *end
+            }),
+            Skip::Commit(sha) => mark(
+                &mut skipped,
+                toolchains,
+                commits,
+                |_, commit| matches!(commit, Some(c) if c.starts_with(sha.as_str())),
+            ),
+            Skip::Commits(start, end) => {
+                if let Some(range) = commit_range(start, end, toolchains, commits, date_of) {
+                    for s in &mut skipped[range] {
+                        *s = true;
+                    }
+                }
+            }
+        }
+    }
+    skipped
+}
+
+/// Returns the positions of the toolchains within the range of commits from
+/// `start` to `end`, if there are any.
+///
+/// An end which is not among the toolchains is placed by its date, so that a
+/// range which overlaps or encloses the bisection covers the toolchains within
+/// it. If its date is unknown too, it is taken to be past the start or end of
+/// the bisection, unless neither end can be placed.
+fn commit_range(
+    start: &str,
+    end: &str,
+    toolchains: &[Toolchain],
+    commits: &[Commit],
+    date_of: &dyn Fn(&str) -> Option<GitDate>,
+) -> Option<RangeInclusive<usize>> {
+    if toolchains.is_empty() || commits.len() != toolchains.len() {
+        return None;
+    }
+    let position = |sha: &str| {
+        toolchains.iter().position(|t| match &t.spec {
+            ToolchainSpec::Ci { commit, .. } => commit.starts_with(sha),
+            _ => false,
+        })
+    };
+    // `None` if the end cannot be placed, and `Some(None)` if the range
+    // misses the bisection.
+    let first = match position(start) {
+        Some(i) => Some(Some(i)),
+        None => date_of(start).map(|date| commits.iter().position(|c| c.date >= date)),
+    };
+    let last = match position(end) {
+        Some(i) => Some(Some(i)),
+        None => date_of(end).map(|date| commits.iter().rposition(|c| c.date <= date)),
+    };
+    let (first, last) = match (first, last) {
+        (None, None) => return None,
+        (first, last) => (
+            first.unwrap_or(Some(0))?,
+            last.unwrap_or(Some(toolchains.len() - 1))?,
+        ),
+    };
+    (first <= last).then_some(first..=last)
+}
+
+/// Marks the toolchains for which `f` is true, given their date and commit.
+fn mark(
+    skipped: &mut [bool],
+    toolchains: &[Toolchain],
+    commits: &[Commit],
+    f: impl Fn(GitDate, Option<&str>) -> bool,
+) {
+    for (i, t) in toolchains.iter().enumerate() {
+        let matches = match &t.spec {
+            ToolchainSpec::Nightly { date } => f(*date, None),
+            ToolchainSpec::Ci { commit, .. } => match commits.get(i) {
+                Some(c) => f(c.date, Some(commit)),
+                None => false,
+            },
+            ToolchainSpec::Stable { .. } | ToolchainSpec::Beta { .. } => false,
+        };
+        skipped[i] |= matches;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> GitDate {
+        parse_to_utc_date(s).unwrap()
+    }
+
+    fn toolchain(spec: ToolchainSpec) -> Toolchain {
+        Toolchain {
+            spec,
+            host: "x86_64-unknown-linux-gnu".to_string(),
+            std_targets: vec!["x86_64-unknown-linux-gnu".to_string()],
+        }
+    }
+
+    #[test]
+    fn parses_skips() {
+        assert_eq!(
+            "2022-08-03".parse::<Skip>().unwrap(),
+            Skip::Date(date("2022-08-03"))
+        );
+        assert_eq!(
+            "abc123".parse::<Skip>().unwrap(),
+            Skip::Commit("abc123".to_string())
+        );
+        assert_eq!(
+            "2022-08-03..2022-08-09".parse::<Skip>().unwrap(),
+            Skip::Dates(date("2022-08-03"), date("2022-08-09"))
+        );
+        assert_eq!(
+            "abc..def".parse::<Skip>().unwrap(),
+            Skip::Commits("abc".to_string(), "def".to_string())
+        );
+        assert!("2022-08-09..2022-08-03".parse::<Skip>().is_err());
+        assert!("2022-08-03..abc".parse::<Skip>().is_err());
+        assert!("abc..".parse::<Skip>().is_err());
+    }
+
+    #[test]
+    fn reads_skip_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("skip.txt");
+        fs::write(
+            &path,
+            "# cargo was broken\n2022-08-03..2022-08-09\n\nabc123\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_file(&path).unwrap(),
+            [
+                Skip::Dates(date("2022-08-03"), date("2022-08-09")),
+                Skip::Commit("abc123".to_string()),
+            ]
+        );
+
+        fs::write(&path, "2022-08-03\n2022-08-09..2022-08-03\n").unwrap();
+        let error = format!("{:#}", read_file(&path).unwrap_err());
+        assert!(error.contains("skip.txt:2"), "{error}");
+    }
+
+    #[test]
+    fn skips_nightlies_by_date() {
+        let toolchains: Vec<Toolchain> = (1..=9)
+            .map(|day| {
+                toolchain(ToolchainSpec::Nightly {
+                    date: date(&format!("2022-08-0{day}")),
+                })
+            })
+            .collect();
+        let skips = [
+            Skip::Date(date("2022-08-02")),
+            Skip::Dates(date("2022-08-05"), date("2022-08-06")),
+            Skip::Commit("abc".to_string()),
+        ];
+        let skipped = skipped(&skips, &toolchains, &[], &|_| None);
+        assert_eq!(
+            skipped,
+            [false, true, false, false, true, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn skips_commits() {
+        let commits: Vec<Commit> = ["a1", "b2", "c3", "d4", "e5"]
+            .iter()
+            .zip([
+                "2022-08-01",
+                "2022-08-01",
+                "2022-08-02",
+                "2022-08-03",
+                "2022-08-03",
+            ])
+            .map(|(sha, day)| Commit {
+                sha: sha.to_string(),
+                date: date(day),
+                summary: String::new(),
+            })
+            .collect();
+        let toolchains: Vec<Toolchain> = commits
+            .iter()
+            .map(|c| {
+                toolchain(ToolchainSpec::Ci {
+                    commit: c.sha.clone(),
+                    alt: false,
+                })
+            })
+            .collect();
+        let date_of = |sha: &str| match sha {
+            "0a" => Some(date("2022-07-31")),
+            "f0" => Some(date("2022-08-05")),
+            "f1" => Some(date("2022-08-06")),
+            _ => None,
+        };
+        let skipped = |skips: &[Skip]| skipped(skips, &toolchains, &commits, &date_of);
+
+        assert_eq!(
+            skipped(&[Skip::Commit("b".to_string())]),
+            [false, true, false, false, false]
+        );
+        assert_eq!(
+            skipped(&[Skip::Commits("b2".to_string(), "d4".to_string())]),
+            [false, true, true, true, false]
+        );
+        // The start of the range is before the bisection.
+        assert_eq!(
+            skipped(&[Skip::Commits("0f".to_string(), "b2".to_string())]),
+            [true, true, false, false, false]
+        );
+        // The range encloses the bisection.
+        assert_eq!(
+            skipped(&[Skip::Commits("0a".to_string(), "f0".to_string())]),
+            [true; 5]
+        );
+        assert_eq!(
+            skipped(&[Skip::Commits("c3".to_string(), "f0".to_string())]),
+            [false, false, true, true, true]
+        );
+        // The range is after the bisection.
+        assert_eq!(
+            skipped(&[Skip::Commits("f0".to_string(), "f1".to_string())]),
+            [false; 5]
+        );
+        assert_eq!(
+            skipped(&[Skip::Commits("0f".to_string(), "ff".to_string())]),
+            [false; 5]
+        );
+        assert_eq!(
+            skipped(&[Skip::Date(date("2022-08-02"))]),
+            [false, false, true, false, false]
+        );
+    }
+}
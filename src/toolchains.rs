@@ -1,35 +1,49 @@
 use std::fmt;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use chrono::{Date, NaiveDate, Utc};
 use colored::Colorize;
 use dialoguer::Select;
 use flate2::read::GzDecoder;
 use log::debug;
-use pbr::{ProgressBar, Units};
+use pbr::{MultiBar, Pipe, ProgressBar, Units};
 use reqwest::blocking::{Client, Response};
-use reqwest::header::CONTENT_LENGTH;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
 use rustc_version::Channel;
+use sha2::{Digest, Sha256};
 use tar::Archive;
-use tee::TeeReader;
 use xz2::read::XzDecoder;
 
-use crate::Config;
+use crate::cache::Cache;
+use crate::manifest::{Artifact, Manifest};
+use crate::mirrors;
+use crate::command;
+use crate::container::Container;
+use crate::perf::{self, Metric};
+use crate::{Config, Progress, RegressOn, Reporter};
 
 pub type GitDate = Date<Utc>;
 
 pub const YYYY_MM_DD: &str = "%Y-%m-%d";
 
-pub(crate) const NIGHTLY_SERVER: &str = "https://static.rust-lang.org/dist";
-const CI_SERVER: &str = "https://s3-us-west-1.amazonaws.com/rust-lang-ci2";
-
+/// Why a toolchain could not be installed.
 #[derive(thiserror::Error, Debug)]
-pub(crate) enum InstallError {
+pub enum InstallError {
     #[error("Could not find {spec}; url: {url}")]
     NotFound { url: String, spec: ToolchainSpec },
+    #[error("Components of {spec} are unavailable: {}", .components.join(", "))]
+    Unavailable {
+        spec: ToolchainSpec,
+        components: Vec<String>,
+    },
     #[error("Could not download toolchain: {0}")]
     Download(#[source] DownloadError),
     #[error("Could not create tempdir: {0}")]
@@ -44,17 +58,50 @@ pub(crate) enum InstallError {
     },
 }
 
-#[derive(Debug)]
-pub(crate) enum TestOutcome {
+/// Whether a toolchain has the regression, according to the test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The toolchain behaves like the start of the range.
     Baseline,
+    /// The toolchain has the regression.
     Regressed,
+    /// The test was killed after running for longer than `--timeout`, and
+    /// counts according to `--timeout-policy`.
+    TimedOut,
+    /// The test exited with 125, or the predicate could not tell, so the
+    /// toolchain counts as untested.
+    Skip,
+}
+
+/// The outcome of testing a toolchain, along with details about the test run.
+#[derive(Debug)]
+pub struct TestRun {
+    pub outcome: TestOutcome,
+    /// Exit code of the test command, `None` if it was terminated by a signal
+    /// or could not be run.
+    pub status: Option<i32>,
+    pub duration: Duration,
+    /// Measurements taken with `--regress=slower-than`.
+    pub measurements: Option<Vec<f64>>,
+    /// Peak resident set size of the test command in bytes, where supported.
+    pub peak_rss: Option<u64>,
 }
 
+/// A single run of the test command.
+struct Execution {
+    output: process::Output,
+    elapsed: Duration,
+    peak_rss: Option<u64>,
+    timed_out: bool,
+}
+
+/// A toolchain to install and test: a build of the compiler for `host`, with
+/// the standard library for each of `std_targets`.
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub(crate) struct Toolchain {
-    pub(crate) spec: ToolchainSpec,
-    pub(crate) host: String,
-    pub(crate) std_targets: Vec<String>,
+pub struct Toolchain {
+    pub spec: ToolchainSpec,
+    pub host: String,
+    pub std_targets: Vec<String>,
 }
 
 impl fmt::Display for Toolchain {
@@ -64,7 +111,10 @@ impl fmt::Display for Toolchain {
 }
 
 impl Toolchain {
-    pub(crate) fn rustup_name(&self) -> String {
+    /// The name of the toolchain in the toolchains directory, which rustup
+    /// also knows it by.
+    #[must_use]
+    pub fn rustup_name(&self) -> String {
         match self.spec {
             ToolchainSpec::Ci { ref commit, alt } => {
                 let alt_s = if alt {
@@ -79,6 +129,12 @@ impl Toolchain {
             ToolchainSpec::Nightly { ref date } => {
                 format!("bisector-nightly-{}-{}", date.format(YYYY_MM_DD), self.host)
             }
+            ToolchainSpec::Beta { ref date } => {
+                format!("bisector-beta-{}-{}", date.format(YYYY_MM_DD), self.host)
+            }
+            ToolchainSpec::Stable { ref version } => {
+                format!("bisector-stable-{version}-{}", self.host)
+            }
         }
     }
     /// This returns the date of the default toolchain, if it is a nightly toolchain.
@@ -101,13 +157,22 @@ impl Toolchain {
         false
     }
 
-    pub(crate) fn install(
-        &self,
-        client: &Client,
-        dl_params: &DownloadParams,
-    ) -> Result<(), InstallError> {
-        let tc_stdstream_str = format!("{self}");
-        eprintln!("installing {}", tc_stdstream_str.green());
+    /// Downloads the toolchain and unpacks it into the toolchains directory,
+    /// unless it is already installed there.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the toolchain or one of its components is not available, or
+    /// if it cannot be downloaded or unpacked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rustc --print sysroot` prints something other than a line,
+    /// when linking the default nightly.
+    pub fn install(&self, client: &Client, dl_params: &DownloadParams) -> Result<(), InstallError> {
+        if dl_params.progress {
+            dl_params.reporter.report(&Progress::Installing(self));
+        }
         let tmpdir = tempfile::Builder::new()
             .prefix(&self.rustup_name())
             .tempdir_in(&dl_params.tmp_dir)
@@ -164,11 +229,121 @@ impl Toolchain {
         }
 
         debug!("installing via download {}", self);
+        self.download(client, dl_params, Destination::Unpack(tmpdir.path()))?;
 
-        let location = match self.spec {
-            ToolchainSpec::Ci { ref commit, .. } => commit.to_string(),
-            ToolchainSpec::Nightly { ref date } => date.format(YYYY_MM_DD).to_string(),
-        };
+        fs::rename(tmpdir.into_path(), dest).map_err(InstallError::Move)
+    }
+
+    /// Downloads the tarballs of this toolchain without unpacking them, along
+    /// with its manifest, into `dir` at their paths on the server, so that
+    /// `dir` can be used as a `file://` mirror without network access.
+    pub(crate) fn mirror(
+        &self,
+        client: &Client,
+        dl_params: &DownloadParams,
+        dir: &Path,
+    ) -> Result<(), InstallError> {
+        if dl_params.progress {
+            let tc_stdstream_str = format!("{self}");
+            status!(
+                dl_params.reporter,
+                "downloading {}",
+                tc_stdstream_str.green()
+            );
+        }
+        let mut files = Vec::new();
+        match self.spec {
+            ToolchainSpec::Ci { .. } => {}
+            ToolchainSpec::Nightly { .. } => {
+                files.push(self.manifest_path());
+                files.push(format!(
+                    "{}/channel-rust-nightly-git-commit-hash.txt",
+                    self.spec.location()
+                ));
+            }
+            ToolchainSpec::Beta { .. } | ToolchainSpec::Stable { .. } => {
+                files.push(self.manifest_path());
+            }
+        }
+        for path in files {
+            let urls = mirrors::urls(&dl_params.servers, &path);
+            let text = mirrors::first_available(&urls, &dl_params.reporter, |url| {
+                get_text(client, url, dl_params.attempts, &dl_params.reporter)
+            })
+            .map_err(|e| self.install_error(e))?;
+            write_to_mirror(text.as_bytes(), &dir.join(path.trim_start_matches('/')))
+                .map_err(|e| InstallError::Download(DownloadError::Io(e)))?;
+        }
+        self.download(client, dl_params, Destination::Mirror(dir))
+    }
+
+    /// Downloads the components of this toolchain into `dest`, all at the
+    /// same time.
+    fn download(
+        &self,
+        client: &Client,
+        dl_params: &DownloadParams,
+        dest: Destination<'_>,
+    ) -> Result<(), InstallError> {
+        let downloads = self.downloads(client, dl_params)?;
+        // Like the rest of the progress, the bars go to stderr.
+        let multibar = MultiBar::on(io::stderr());
+        let draw_bars = dl_params.progress && dl_params.reporter.prints_to_stderr();
+        let bars: Vec<Option<Bar>> = downloads
+            .iter()
+            .map(|download| {
+                dl_params.progress.then(|| {
+                    if draw_bars {
+                        Bar::new(&multibar, download.name())
+                    } else {
+                        Bar::reported(download.name(), &dl_params.reporter)
+                    }
+                })
+            })
+            .collect();
+        thread::scope(|scope| {
+            let handles: Vec<_> = downloads
+                .iter()
+                .zip(bars)
+                .map(|(download, mut bar)| {
+                    scope.spawn(move || download.fetch(client, dest, dl_params, bar.as_mut()))
+                })
+                .collect();
+            // All bars must be created before listening, which only returns
+            // once they are finished.
+            if draw_bars {
+                scope.spawn(|| multibar.listen());
+            }
+            handles
+                .into_iter()
+                .map(|handle| match handle.join() {
+                    Ok(result) => result,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect::<Result<Vec<()>, _>>()
+        })
+        .map_err(|e| self.install_error(e))?;
+        Ok(())
+    }
+
+    /// Lists the tarballs of the requested components.
+    fn downloads(
+        &self,
+        client: &Client,
+        dl_params: &DownloadParams,
+    ) -> Result<Vec<Download>, InstallError> {
+        let location = self.spec.location();
+        let channel = self.spec.channel();
+
+        if let Some(manifest) = self.manifest(client, dl_params)? {
+            let artifacts =
+                self.resolve_components(&manifest, dl_params)
+                    .map_err(|components| InstallError::Unavailable {
+                        spec: self.spec.clone(),
+                        components,
+                    })?;
+            return Ok(artifacts.into_iter().map(Download::Artifact).collect());
+        }
 
         let components = dl_params
             .components
@@ -176,41 +351,128 @@ impl Toolchain {
             .map(|component| {
                 if component == "rust-src" {
                     // rust-src is target-independent
-                    "rust-src-nightly".to_string()
+                    format!("rust-src-{channel}")
                 } else {
-                    format!("{component}-nightly-{}", self.host)
+                    format!("{component}-{channel}-{}", self.host)
                 }
             })
             .chain(
                 self.std_targets
                     .iter()
-                    .map(|target| format!("rust-std-nightly-{target}")),
+                    .map(|target| format!("rust-std-{channel}-{target}")),
             );
 
-        for component in components {
-            download_tarball(
-                client,
-                &component,
-                &format!("{}/{location}/{component}.tar", dl_params.url_prefix),
-                tmpdir.path(),
-            )
-            .map_err(|e| {
-                if let DownloadError::NotFound(url) = e {
-                    InstallError::NotFound {
-                        url,
-                        spec: self.spec.clone(),
-                    }
-                } else {
-                    InstallError::Download(e)
-                }
-            })?;
+        // The CI bucket does not publish checksums next to its artifacts.
+        let checksummed = !matches!(self.spec, ToolchainSpec::Ci { .. });
+        Ok(components
+            .map(|component| Download::Tarball {
+                path: format!("{location}/{component}.tar"),
+                name: component,
+                checksummed,
+            })
+            .collect())
+    }
+
+    fn install_error(&self, e: DownloadError) -> InstallError {
+        if let DownloadError::NotFound(url) = e {
+            InstallError::NotFound {
+                url,
+                spec: self.spec.clone(),
+            }
+        } else {
+            InstallError::Download(e)
         }
+    }
 
-        fs::rename(tmpdir.into_path(), dest).map_err(InstallError::Move)
+    /// Fetches the manifest listing the available components, which every
+    /// toolchain but CI artifacts comes with.
+    fn manifest(
+        &self,
+        client: &Client,
+        dl_params: &DownloadParams,
+    ) -> Result<Option<Manifest>, InstallError> {
+        if let ToolchainSpec::Ci { .. } = self.spec {
+            return Ok(None);
+        }
+        let path = self.manifest_path();
+        let urls = mirrors::urls(&dl_params.servers, &path);
+        match mirrors::first_available(&urls, &dl_params.reporter, |url| {
+            Manifest::fetch(client, url, dl_params.attempts, &dl_params.reporter)
+        }) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(DownloadError::NotFound(_)) => {
+                debug!("no manifest at <{}>", path);
+                Ok(None)
+            }
+            Err(e) => Err(self.install_error(e)),
+        }
+    }
+
+    /// Path of the manifest of this toolchain on the dist server.
+    fn manifest_path(&self) -> String {
+        format!(
+            "{}/channel-rust-{}.toml",
+            self.spec.location(),
+            self.spec.channel()
+        )
+    }
+
+    /// Returns the requested components which are not available in this
+    /// toolchain, without downloading any of them.
+    pub(crate) fn missing_components(
+        &self,
+        client: &Client,
+        dl_params: &DownloadParams,
+    ) -> Result<Vec<String>, InstallError> {
+        match self.manifest(client, dl_params)? {
+            Some(manifest) => match self.resolve_components(&manifest, dl_params) {
+                Ok(_) => Ok(Vec::new()),
+                Err(missing) => Ok(missing),
+            },
+            // Without a manifest, only trying to download the components
+            // tells whether they are available.
+            None => Ok(Vec::new()),
+        }
     }
 
-    pub(crate) fn remove(&self, dl_params: &DownloadParams) -> io::Result<()> {
-        eprintln!("uninstalling {}", self);
+    /// Looks up the tarballs of the requested components in `manifest`,
+    /// failing with all components which are unavailable in this toolchain.
+    fn resolve_components(
+        &self,
+        manifest: &Manifest,
+        dl_params: &DownloadParams,
+    ) -> Result<Vec<Artifact>, Vec<String>> {
+        let requested = dl_params
+            .components
+            .iter()
+            .map(|component| (component.as_str(), self.host.as_str()))
+            .chain(
+                self.std_targets
+                    .iter()
+                    .map(|target| ("rust-std", target.as_str())),
+            );
+        let mut artifacts = Vec::new();
+        let mut unavailable = Vec::new();
+        for (component, target) in requested {
+            match manifest.artifact(component, target) {
+                Some(artifact) => artifacts.push(artifact),
+                None => unavailable.push(format!("{component} ({target})")),
+            }
+        }
+        if unavailable.is_empty() {
+            Ok(artifacts)
+        } else {
+            Err(unavailable)
+        }
+    }
+
+    /// Removes the installed toolchain.
+    ///
+    /// # Errors
+    ///
+    /// Fails if its directory cannot be removed.
+    pub fn remove(&self, dl_params: &DownloadParams) -> io::Result<()> {
+        status!(dl_params.reporter, "uninstalling {self}");
         self.do_remove(dl_params)
     }
 
@@ -223,14 +485,22 @@ impl Toolchain {
 
         // Guard against destroying directories that this tool didn't create.
         assert!(
-            rustup_name.starts_with("bisector-nightly") || rustup_name.starts_with("bisector-ci")
+            rustup_name.starts_with("bisector-nightly")
+                || rustup_name.starts_with("bisector-ci")
+                || rustup_name.starts_with("bisector-beta")
+                || rustup_name.starts_with("bisector-stable")
         );
 
         let dir = dl_params.install_dir.join(rustup_name);
         fs::remove_dir_all(&dir)
     }
 
-    pub(crate) fn run_test(&self, cfg: &Config) -> process::Output {
+    /// Runs the test, counting the instructions it executes into
+    /// `perf_stat_output` if given.
+    ///
+    /// Fails if the environment of the test cannot be set up, e.g. the
+    /// sandbox.
+    fn run_test(&self, cfg: &Config, perf_stat_output: Option<&Path>) -> anyhow::Result<Execution> {
         if !cfg.args.preserve_target {
             let _ = fs::remove_dir_all(
                 cfg.args
@@ -239,119 +509,271 @@ impl Toolchain {
             );
         }
 
-        let mut cmd = match (cfg.args.script.as_ref(), cfg.args.timeout) {
-            (Some(script), None) => {
+        let mut cmd = match cfg.args.script.as_ref() {
+            Some(script) => {
                 let mut cmd = Command::new(script);
                 cmd.env("RUSTUP_TOOLCHAIN", self.rustup_name());
                 cmd.args(&cfg.args.command_args);
                 cmd
             }
-            (None, None) => {
+            None => {
                 let mut cmd = Command::new("cargo");
-                cmd.arg(&format!("+{}", self.rustup_name()));
-                if cfg.args.command_args.is_empty() {
-                    cmd.arg("build");
-                } else {
-                    cmd.args(&cfg.args.command_args);
+                // Containers have the toolchain in their `PATH` instead.
+                if cfg.container.is_none() {
+                    cmd.arg(&format!("+{}", self.rustup_name()));
                 }
-                cmd
-            }
-            (Some(script), Some(timeout)) => {
-                let mut cmd = Command::new("timeout");
-                cmd.arg(timeout.to_string());
-                cmd.arg(script);
-                cmd.args(&cfg.args.command_args);
-                cmd.env("RUSTUP_TOOLCHAIN", self.rustup_name());
-                cmd
-            }
-            (None, Some(timeout)) => {
-                let mut cmd = Command::new("timeout");
-                cmd.arg(timeout.to_string());
-                cmd.arg("cargo");
-                cmd.arg(format!("+{}", self.rustup_name()));
                 if cfg.args.command_args.is_empty() {
-                    cmd.arg("build");
+                    cmd.args(cfg.args.default_cargo_args());
                 } else {
                     cmd.args(&cfg.args.command_args);
                 }
                 cmd
             }
         };
+        if let Some(output) = perf_stat_output {
+            cmd = perf::perf_stat(&cmd, output);
+        }
         cmd.current_dir(&cfg.args.test_dir);
         cmd.env("CARGO_TARGET_DIR", format!("target-{}", self.rustup_name()));
         cmd.env("CARGO_BUILD_TARGET", &cfg.target);
 
-        // let `cmd` capture stderr for us to process afterward.
-        let must_capture_output = cfg.args.regress.must_process_stderr();
-        let emit_output = cfg.args.emit_cargo_output() || cfg.args.prompt;
+        let container_name = cfg.container.as_ref().map(|container| {
+            let name = Container::unique_name();
+            cmd = container.wrap(
+                &cmd,
+                &name,
+                &cfg.toolchains_path.join(self.rustup_name()),
+                &[&cfg.args.test_dir],
+            );
+            name
+        });
 
-        let default_stdio = if must_capture_output {
-            Stdio::piped
-        } else if emit_output {
-            Stdio::inherit
-        } else {
-            Stdio::null
-        };
+        // Removed once the test is done.
+        let mut sandbox_tmp = None;
+        if let Some(ref sandbox) = cfg.sandbox {
+            let tmp = sandbox_tmp.insert(
+                tempfile::tempdir()
+                    .context("failed to create a temporary directory for the test")?,
+            );
+            sandbox
+                .apply(&mut cmd, &cfg.args.test_dir, tmp.path())
+                .with_context(|| format!("failed to set up the sandbox for {cmd:?}"))?;
+        }
 
-        cmd.stdout(default_stdio());
-        cmd.stderr(default_stdio());
+        let shown = redirect_output(cfg, &mut cmd);
 
-        let output = match cmd.output() {
-            Ok(output) => output,
+        let start = Instant::now();
+        let timeout = cfg
+            .args
+            .timeout
+            .map(|secs| Duration::from_secs(secs as u64));
+        let finished = match command::run(&mut cmd, timeout, shown.then_some(&cfg.reporter)) {
+            Ok(finished) => finished,
             Err(err) => {
                 panic!("thiserror::Errored to run {:?}: {:?}", cmd, err);
             }
         };
+        let elapsed = start.elapsed();
+        let output = finished.output;
+        if finished.timed_out {
+            status!(
+                cfg.reporter,
+                "{self} timed out after {}s",
+                cfg.args.timeout.unwrap_or_default()
+            );
+            // Killing the client does not stop the container.
+            if let (Some(container), Some(name)) = (&cfg.container, &container_name) {
+                container.kill(name);
+            }
+        }
 
-        // if we captured the stdout above but still need to emit it, then do so now
-        if must_capture_output && emit_output {
-            io::stdout().write_all(&output.stdout).unwrap();
-            io::stderr().write_all(&output.stderr).unwrap();
+        Ok(Execution {
+            output,
+            elapsed,
+            peak_rss: finished.peak_rss,
+            timed_out: finished.timed_out,
+        })
+    }
+
+    /// Runs the test `--perf-runs` times, measuring `--perf-metric`, and
+    /// compares the measurements to those of the baseline.
+    fn measure(
+        &self,
+        cfg: &Config,
+    ) -> anyhow::Result<(TestOutcome, process::ExitStatus, Option<Vec<f64>>)> {
+        let perf_stat_output = match cfg.args.perf_metric {
+            Metric::WallTime | Metric::MaxRss => None,
+            Metric::Instructions => Some(
+                tempfile::NamedTempFile::new()
+                    .context("failed to create a file for the output of `perf stat`")?,
+            ),
+        };
+        let mut samples = Vec::new();
+        let mut status = None;
+        for run in 1..=cfg.args.perf_runs {
+            debug!("measuring run {} of {}", run, cfg.args.perf_runs);
+            let Execution {
+                output,
+                elapsed,
+                peak_rss,
+                timed_out,
+            } = self.run_test(
+                cfg,
+                perf_stat_output.as_ref().map(tempfile::NamedTempFile::path),
+            )?;
+            if timed_out {
+                return Ok((TestOutcome::TimedOut, output.status, None));
+            }
+            if !output.status.success() {
+                warning!(
+                    cfg.reporter,
+                    "{self} failed with exit code {:?} instead of being measured",
+                    output.status.code()
+                );
+                return Ok((
+                    cfg.outcome_of_output(self, &output, elapsed),
+                    output.status,
+                    None,
+                ));
+            }
+            #[allow(clippy::cast_precision_loss)]
+            samples.push(match cfg.args.perf_metric {
+                Metric::WallTime => elapsed.as_secs_f64(),
+                Metric::Instructions => {
+                    perf::read_perf_stat(perf_stat_output.as_ref().expect("created above").path())
+                        .context("failed to read the output of `perf stat`")?
+                }
+                Metric::MaxRss => peak_rss
+                    .context("the peak memory usage of the test could not be measured")?
+                    as f64,
+            });
+            status = Some(output.status);
         }
-        output
+        Ok((
+            cfg.perf_outcome(&samples),
+            status.expect("at least two runs"),
+            Some(samples),
+        ))
     }
 
-    pub(crate) fn test(&self, cfg: &Config) -> TestOutcome {
-        eprintln!("testing...");
-        let outcome = if cfg.args.prompt {
-            loop {
-                let output = self.run_test(cfg);
-                let status = output.status;
+    /// Runs the test until the user decides whether it has the regression.
+    fn prompt(&self, cfg: &Config) -> anyhow::Result<(TestOutcome, process::ExitStatus)> {
+        loop {
+            let Execution {
+                output,
+                elapsed,
+                timed_out,
+                ..
+            } = self.run_test(cfg, None)?;
+            let status = output.status;
 
-                //timeout returns exit code 124 on expiration
-                if status.code() == Some(124) {
-                    match cfg.args.timeout {
-                        Some(_) => break TestOutcome::Regressed,
-                        None => panic!("Process timed out but no timeout was specified. Please check host configuration for timeouts and try again.")
-                    }
-                }
+            if timed_out {
+                return Ok((TestOutcome::TimedOut, status));
+            }
 
-                eprintln!("\n\n{} finished with exit code {:?}.", self, status.code());
-                eprintln!("please select an action to take:");
+            status!(
+                cfg.reporter,
+                "\n\n{} finished with exit code {:?}.",
+                self,
+                status.code()
+            );
+            status!(cfg.reporter, "please select an action to take:");
 
-                let default_choice = match cfg.default_outcome_of_output(&output) {
-                    TestOutcome::Regressed => 0,
-                    TestOutcome::Baseline => 1,
-                };
+            let default_choice = match cfg.outcome_of_output(self, &output, elapsed) {
+                TestOutcome::Regressed | TestOutcome::TimedOut => 0,
+                TestOutcome::Baseline => 1,
+                TestOutcome::Skip => 2,
+            };
 
-                match Select::new()
-                    .items(&["mark regressed", "mark baseline", "retry"])
-                    .default(default_choice)
-                    .interact()
-                    .unwrap()
-                {
-                    0 => break TestOutcome::Regressed,
-                    1 => break TestOutcome::Baseline,
-                    2 => continue,
-                    _ => unreachable!(),
-                }
+            match Select::new()
+                .items(&["mark regressed", "mark baseline", "skip", "retry"])
+                .default(default_choice)
+                .interact()
+                .unwrap()
+            {
+                0 => return Ok((TestOutcome::Regressed, status)),
+                1 => return Ok((TestOutcome::Baseline, status)),
+                2 => return Ok((TestOutcome::Skip, status)),
+                3 => continue,
+                _ => unreachable!(),
             }
+        }
+    }
+
+    /// Runs the test of the bisection with the installed toolchain, and
+    /// decides whether it has the regression. If the test cannot be set up,
+    /// e.g. its sandbox, the toolchain is skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the test command cannot be run at all.
+    #[must_use]
+    pub fn test(&self, cfg: &Config) -> TestRun {
+        status!(cfg.reporter, "testing...");
+        let start = Instant::now();
+        let mut measurements = None;
+        let mut peak_rss = None;
+        let tested = if cfg.args.regress == RegressOn::SlowerThan {
+            self.measure(cfg).map(|(outcome, status, samples)| {
+                measurements = samples;
+                (outcome, status)
+            })
+        } else if cfg.args.prompt {
+            self.prompt(cfg)
         } else {
-            let output = self.run_test(cfg);
-            cfg.default_outcome_of_output(&output)
+            self.run_test(cfg, None).map(|execution| {
+                peak_rss = execution.peak_rss;
+                let outcome = match peak_rss {
+                    _ if execution.timed_out => TestOutcome::TimedOut,
+                    Some(peak_rss)
+                        if cfg.args.regress == RegressOn::PeakMemory
+                            && execution.output.status.success() =>
+                    {
+                        cfg.memory_outcome(peak_rss)
+                    }
+                    _ => cfg.outcome_of_output(self, &execution.output, execution.elapsed),
+                };
+                (outcome, execution.output.status)
+            })
         };
+        // The toolchain may well be fine, so it is left untested rather than
+        // giving up on the whole bisection.
+        let (outcome, status) = match tested {
+            Ok((outcome, status)) => (outcome, status.code()),
+            Err(e) => {
+                warning!(cfg.reporter, "could not test {self}: {e:#}");
+                (TestOutcome::Skip, None)
+            }
+        };
+
+        TestRun {
+            outcome,
+            status,
+            duration: start.elapsed(),
+            measurements,
+            peak_rss,
+        }
+    }
+}
 
-        outcome
+/// Lets `cmd` capture its output for the predicate to process afterward, or
+/// otherwise show it or discard it. Returns whether captured output has to be
+/// shown by handing it to the reporter.
+///
+/// When printing to stderr, output which is not captured is written there
+/// directly, so that the test still writes to a terminal.
+fn redirect_output(cfg: &Config, cmd: &mut Command) -> bool {
+    let capture = cfg.predicate.needs_output();
+    let show = cfg.args.emit_output();
+    if show && !capture && cfg.reporter.prints_to_stderr() {
+        cmd.stdout(io::stderr()).stderr(Stdio::inherit());
+        false
+    } else if show || capture {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        show
+    } else {
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        false
     }
 }
 
@@ -359,10 +781,50 @@ pub fn parse_to_utc_date(s: &str) -> chrono::ParseResult<GitDate> {
     NaiveDate::parse_from_str(s, YYYY_MM_DD).map(|date| Date::from_utc(date, Utc))
 }
 
+/// Which build of the compiler a toolchain is.
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub(crate) enum ToolchainSpec {
-    Ci { commit: String, alt: bool },
-    Nightly { date: GitDate },
+pub enum ToolchainSpec {
+    /// The CI build of a commit, with extra assertions if `alt`.
+    Ci {
+        commit: String,
+        alt: bool,
+    },
+    Nightly {
+        date: GitDate,
+    },
+    Beta {
+        date: GitDate,
+    },
+    /// A stable release, such as `1.62.0`.
+    Stable {
+        version: String,
+    },
+}
+
+impl ToolchainSpec {
+    /// Directory of the toolchain on its server. Stable releases are at the
+    /// root of the dist server, everything else is in a directory for its date
+    /// or commit.
+    fn location(&self) -> String {
+        match self {
+            ToolchainSpec::Ci { commit, .. } => format!("/{commit}"),
+            ToolchainSpec::Nightly { date } | ToolchainSpec::Beta { date } => {
+                format!("/{}", date.format(YYYY_MM_DD))
+            }
+            ToolchainSpec::Stable { .. } => String::new(),
+        }
+    }
+
+    /// The channel part of the component tarball names, such as
+    /// `rustc-nightly-x86_64-unknown-linux-gnu` or `rustc-1.62.0-x86_64-unknown-linux-gnu`.
+    fn channel(&self) -> &str {
+        match self {
+            // CI artifacts are named like nightlies
+            ToolchainSpec::Ci { .. } | ToolchainSpec::Nightly { .. } => "nightly",
+            ToolchainSpec::Beta { .. } => "beta",
+            ToolchainSpec::Stable { version } => version,
+        }
+    }
 }
 
 impl fmt::Display for ToolchainSpec {
@@ -377,34 +839,67 @@ impl fmt::Display for ToolchainSpec {
                 write!(f, "{}{}", commit, alt_s)
             }
             ToolchainSpec::Nightly { ref date } => write!(f, "nightly-{}", date.format(YYYY_MM_DD)),
+            ToolchainSpec::Beta { ref date } => write!(f, "beta-{}", date.format(YYYY_MM_DD)),
+            ToolchainSpec::Stable { ref version } => write!(f, "{version}"),
         }
     }
 }
 
+/// Where to download toolchains from and install them to.
 #[derive(Clone, Debug)]
-pub(crate) struct DownloadParams {
-    url_prefix: String,
+pub struct DownloadParams {
+    /// Base URLs of the server to download from and its mirrors, in the
+    /// order to try them.
+    servers: Vec<String>,
     tmp_dir: PathBuf,
     install_dir: PathBuf,
     components: Vec<String>,
     force_install: bool,
+    cache: Option<Cache>,
+    /// Whether to show the progress of downloads, which prefetching in the
+    /// background does not.
+    progress: bool,
+    /// Number of attempts at each download before giving up.
+    attempts: u32,
+    reporter: Reporter,
 }
 
 impl DownloadParams {
-    pub(crate) fn for_ci(cfg: &Config) -> Self {
-        let url_prefix = format!(
-            "{CI_SERVER}/rustc-builds{}",
-            if cfg.args.alt { "-alt" } else { "" }
-        );
+    /// The same parameters for installing toolchains in the background,
+    /// without showing the progress of downloads.
+    pub(crate) fn in_background(&self) -> Self {
+        DownloadParams {
+            progress: false,
+            ..self.clone()
+        }
+    }
 
-        Self::from_cfg_with_url_prefix(cfg, url_prefix)
+    /// Whether components which not every toolchain ships were requested.
+    pub(crate) fn has_optional_components(&self) -> bool {
+        self.components
+            .iter()
+            .any(|component| component != "rustc" && component != "cargo")
+    }
+
+    /// Download parameters for CI builds, which are on the CI artifact server.
+    #[must_use]
+    pub fn for_ci(cfg: &Config) -> Self {
+        let builds = if cfg.args.alt {
+            "/rustc-builds-alt"
+        } else {
+            "/rustc-builds"
+        };
+        Self::from_cfg_with_servers(cfg, mirrors::urls(cfg.mirrors.ci(), builds))
     }
 
-    pub(crate) fn for_nightly(cfg: &Config) -> Self {
-        Self::from_cfg_with_url_prefix(cfg, NIGHTLY_SERVER.to_string())
+    /// Download parameters for nightly, beta and stable toolchains, which are
+    /// all published on the dist server.
+    #[must_use]
+    pub fn for_nightly(cfg: &Config) -> Self {
+        Self::from_cfg_with_servers(cfg, cfg.mirrors.nightly().to_vec())
     }
 
-    fn from_cfg_with_url_prefix(cfg: &Config, url_prefix: String) -> Self {
+    fn from_cfg_with_servers(cfg: &Config, servers: Vec<String>) -> Self {
         let mut components = vec!["rustc".to_string()];
         if !cfg.args.without_cargo {
             components.push("cargo".to_string());
@@ -418,80 +913,681 @@ impl DownloadParams {
         if cfg.args.with_src {
             components.push("rust-src".to_string());
         }
+        if let Some(tool) = cfg.args.tool {
+            for &component in tool.components() {
+                if !components.iter().any(|c| c == component) {
+                    components.push(component.to_string());
+                }
+            }
+        }
         components.extend(cfg.args.components.clone());
 
         DownloadParams {
-            url_prefix,
+            servers,
             tmp_dir: cfg.rustup_tmp_path.clone(),
             install_dir: cfg.toolchains_path.clone(),
             components,
             force_install: cfg.args.force_install,
+            cache: cfg.cache.clone(),
+            progress: true,
+            attempts: cfg.args.download_attempts,
+            reporter: cfg.reporter.clone(),
         }
     }
 }
 
+/// Why a downloaded tarball could not be unpacked.
 #[derive(thiserror::Error, Debug)]
-pub(crate) enum ArchiveError {
+pub enum ArchiveError {
     #[error("thiserror::Errored to parse archive: {0}")]
     Archive(#[source] io::Error),
     #[error("thiserror::Errored to create directory: {0}")]
     CreateDir(#[source] io::Error),
 }
 
+/// Why a file could not be downloaded.
 #[derive(thiserror::Error, Debug)]
-pub(crate) enum DownloadError {
+pub enum DownloadError {
     #[error("Tarball not found at {0}")]
     NotFound(String),
     #[error("A reqwest error occurred: {0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("An archive error occurred: {0}")]
     Archive(#[from] ArchiveError),
+    #[error("Could not parse manifest: {0}")]
+    Manifest(#[source] toml::de::Error),
+    #[error("Could not save download: {0}")]
+    Io(#[source] io::Error),
+    #[error("Checksum mismatch for {url}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 pub(crate) fn download_progress(
     client: &Client,
     name: &str,
     url: &str,
-) -> Result<TeeReader<Response, ProgressBar<io::Stdout>>, DownloadError> {
-    debug!("downloading <{}>...", url);
+    reporter: &Reporter,
+) -> Result<WithProgress<Box<dyn Read>>, DownloadError> {
+    let (body, length): (Box<dyn Read>, u64) = match local_path(url) {
+        Some(path) => {
+            let file = open_local(path, url)?;
+            let length = file.metadata().map_err(DownloadError::Io)?.len();
+            (Box::new(file), length)
+        }
+        None => {
+            let response = get(client, url, DOWNLOAD_ATTEMPTS, reporter)?;
+            let length = content_length(&response).unwrap_or(0);
+            (Box::new(response), length)
+        }
+    };
+    let mut bar = Bar::single(name, reporter);
+    bar.set_total(length);
+    Ok(WithProgress { inner: body, bar })
+}
+
+/// The path of a `file://` URL, for a mirror in a local directory such as one
+/// filled by `--prefetch-to`.
+pub(crate) fn local_path(url: &str) -> Option<&Path> {
+    url.strip_prefix("file://").map(Path::new)
+}
+
+/// Opens the file at `path`, which `url` points to.
+fn open_local(path: &Path, url: &str) -> Result<File, DownloadError> {
+    File::open(path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            DownloadError::NotFound(url.to_string())
+        } else {
+            DownloadError::Io(e)
+        }
+    })
+}
+
+/// Fetches the text at `url`, such as a manifest or a checksum.
+pub(crate) fn get_text(
+    client: &Client,
+    url: &str,
+    attempts: u32,
+    reporter: &Reporter,
+) -> Result<String, DownloadError> {
+    match local_path(url) {
+        Some(path) => {
+            let mut text = String::new();
+            open_local(path, url)?
+                .read_to_string(&mut text)
+                .map_err(DownloadError::Io)?;
+            Ok(text)
+        }
+        None => Ok(get(client, url, attempts, reporter)?.text()?),
+    }
+}
+
+/// Default number of attempts at each download, for `--download-attempts`.
+pub(crate) const DOWNLOAD_ATTEMPTS: u32 = 5;
+
+/// Sends a GET request for `url`, trying up to `attempts` times in total if
+/// it fails with a transient error.
+pub(crate) fn get(
+    client: &Client,
+    url: &str,
+    attempts: u32,
+    reporter: &Reporter,
+) -> Result<Response, DownloadError> {
+    get_from(client, url, 0, &mut Retry::new(attempts, reporter))
+}
+
+/// Sends a GET request for `url`, starting at byte `offset` of the body.
+fn get_from(
+    client: &Client,
+    url: &str,
+    offset: u64,
+    retry: &mut Retry<'_>,
+) -> Result<Response, DownloadError> {
+    loop {
+        debug!("downloading <{}> from byte {}...", url, offset);
+        let mut request = client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        let result = request
+            .send()
+            .map_err(DownloadError::from)
+            .and_then(|response| {
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Err(DownloadError::NotFound(url.to_string()));
+                }
+                Ok(response.error_for_status()?)
+            });
+        match result {
+            Err(e) if is_transient(&e) && retry.wait(&e) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Whether a request which failed with `e` may succeed when sent again.
+fn is_transient(e: &DownloadError) -> bool {
+    match e {
+        DownloadError::Reqwest(e) => match e.status() {
+            Some(status) => {
+                status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::REQUEST_TIMEOUT
+            }
+            None => e.is_timeout() || e.is_connect() || e.is_body(),
+        },
+        _ => false,
+    }
+}
 
-    let response = client.get(url).send()?;
+/// Counts the attempts at a download, and waits between them.
+struct Retry<'a> {
+    attempt: u32,
+    attempts: u32,
+    reporter: &'a Reporter,
+}
 
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        return Err(DownloadError::NotFound(url.to_string()));
+impl<'a> Retry<'a> {
+    fn new(attempts: u32, reporter: &'a Reporter) -> Retry<'a> {
+        Retry {
+            attempt: 1,
+            attempts,
+            reporter,
+        }
     }
-    let response = response.error_for_status()?;
 
-    let length = response
+    /// Waits before the next attempt after a failed one, or returns `false`
+    /// if that was the last one.
+    fn wait(&mut self, error: &dyn fmt::Display) -> bool {
+        if self.attempt >= self.attempts {
+            return false;
+        }
+        let delay = backoff(self.attempt);
+        self.attempt += 1;
+        warning!(
+            self.reporter,
+            "{error}, retrying in {}s (attempt {} of {})",
+            delay.as_secs(),
+            self.attempt,
+            self.attempts
+        );
+        thread::sleep(delay);
+        true
+    }
+}
+
+/// How long to wait after the given failed attempt, doubling from a second
+/// up to 32 seconds.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << (attempt - 1).min(5))
+}
+
+fn content_length(response: &Response) -> Option<u64> {
+    response
         .headers()
         .get(CONTENT_LENGTH)
         .and_then(|c| c.to_str().ok()?.parse().ok())
-        .unwrap_or(0);
-    let mut bar = ProgressBar::new(length);
-    bar.set_units(Units::Bytes);
-    bar.message(&format!("{name}: "));
+}
+
+/// The offset at which the body of a response to a range request starts,
+/// from its `Content-Range` header (`bytes START-END/LENGTH`).
+fn range_start(response: &Response) -> Option<u64> {
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return None;
+    }
+    let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    range
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// A reader which shows the progress of reading it.
+pub(crate) struct WithProgress<R> {
+    inner: R,
+    bar: Bar,
+}
+
+impl<R: Read> Read for WithProgress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bar.add(n as u64);
+        Ok(n)
+    }
+}
+
+/// The progress of a download, shown as a progress bar when printing to
+/// stderr, and otherwise reported as [`Progress::Download`].
+struct Bar {
+    display: Display,
+    name: String,
+    downloaded: u64,
+    total: u64,
+}
+
+enum Display {
+    /// One of the downloads of a toolchain, which are shown together.
+    Multi(ProgressBar<Pipe>),
+    Single(ProgressBar<io::Stderr>),
+    Reported(Reporter),
+}
+
+impl Bar {
+    fn new(multibar: &MultiBar<io::Stderr>, name: &str) -> Bar {
+        let mut bar = multibar.create_bar(0);
+        bar.set_units(Units::Bytes);
+        bar.message(&format!("{name}: "));
+        Bar::with_display(Display::Multi(bar), name)
+    }
+
+    /// A bar shown on its own, or reported if not printing to stderr.
+    fn single(name: &str, reporter: &Reporter) -> Bar {
+        if !reporter.prints_to_stderr() {
+            return Bar::reported(name, reporter);
+        }
+        let mut bar = ProgressBar::on(io::stderr(), 0);
+        bar.set_units(Units::Bytes);
+        bar.message(&format!("{name}: "));
+        Bar::with_display(Display::Single(bar), name)
+    }
+
+    fn reported(name: &str, reporter: &Reporter) -> Bar {
+        Bar::with_display(Display::Reported(reporter.clone()), name)
+    }
 
-    Ok(TeeReader::new(response, bar))
+    fn with_display(display: Display, name: &str) -> Bar {
+        Bar {
+            display,
+            name: name.to_string(),
+            downloaded: 0,
+            total: 0,
+        }
+    }
+
+    /// Sets the length of the download, 0 if unknown.
+    fn set_total(&mut self, total: u64) {
+        self.total = total;
+        match &mut self.display {
+            Display::Multi(bar) => bar.total = total,
+            Display::Single(bar) => bar.total = total,
+            Display::Reported(_) => {}
+        }
+    }
+
+    fn set(&mut self, downloaded: u64) {
+        self.downloaded = downloaded;
+        match &mut self.display {
+            Display::Multi(bar) => {
+                bar.set(downloaded);
+            }
+            Display::Single(bar) => {
+                bar.set(downloaded);
+            }
+            Display::Reported(reporter) => reporter.report(&Progress::Download {
+                name: &self.name,
+                downloaded,
+                total: (self.total > 0).then_some(self.total),
+            }),
+        }
+    }
+
+    fn add(&mut self, n: u64) {
+        self.set(self.downloaded + n);
+    }
+
+    fn finish(&mut self, message: &str) {
+        let message = format!("{}: {message}", self.name);
+        match &mut self.display {
+            Display::Multi(bar) => bar.finish_print(&message),
+            Display::Single(bar) => bar.finish_print(&message),
+            Display::Reported(reporter) => status!(reporter, "{message}"),
+        }
+    }
+}
+
+impl Drop for Bar {
+    fn drop(&mut self) {
+        // The `MultiBar` waits for every bar to finish, including those of
+        // failed downloads.
+        if let Display::Multi(bar) = &self.display {
+            if !bar.is_finish {
+                self.finish("failed");
+            }
+        }
+    }
+}
+
+/// Where the tarballs of a toolchain go.
+#[derive(Clone, Copy)]
+enum Destination<'a> {
+    /// Unpacked into the directory of the toolchain being installed.
+    Unpack(&'a Path),
+    /// Copied as they are into a directory which mirrors the server.
+    Mirror(&'a Path),
 }
 
-fn download_tar_xz(
+/// A tarball of a component.
+enum Download {
+    /// A tarball listed in a channel manifest.
+    Artifact(Artifact),
+    /// A tarball without a manifest, which comes in either format, at `path`
+    /// on the server, and has a checksum published next to it if
+    /// `checksummed`.
+    Tarball {
+        name: String,
+        path: String,
+        checksummed: bool,
+    },
+}
+
+impl Download {
+    fn name(&self) -> &str {
+        match self {
+            Download::Artifact(artifact) => artifact.name(),
+            Download::Tarball { name, .. } => name,
+        }
+    }
+
+    /// Downloads the tarball into `dest`, from the first server which has it.
+    fn fetch(
+        &self,
+        client: &Client,
+        dest: Destination<'_>,
+        dl_params: &DownloadParams,
+        mut bar: Option<&mut Bar>,
+    ) -> Result<(), DownloadError> {
+        match self {
+            Download::Artifact(artifact) => {
+                let (name, sha256) = (artifact.name(), Some(artifact.sha256.as_str()));
+                let urls = mirrors::artifact_urls(&dl_params.servers, &artifact.url);
+                mirrors::first_available(&urls, &dl_params.reporter, |url| {
+                    let tarball = Tarball { name, url, sha256 };
+                    download_to(client, &tarball, dest, dl_params, bar.as_deref_mut())
+                })
+            }
+            Download::Tarball {
+                name,
+                path,
+                checksummed,
+            } => {
+                let urls = mirrors::urls(&dl_params.servers, path);
+                mirrors::first_available(&urls, &dl_params.reporter, |url| {
+                    let checksummed = *checksummed;
+                    let bar = bar.as_deref_mut();
+                    download_tarball(client, name, url, checksummed, dest, dl_params, bar)
+                })
+            }
+        }
+    }
+}
+
+/// A tarball to download, and its checksum if it is known.
+struct Tarball<'a> {
+    name: &'a str,
+    url: &'a str,
+    sha256: Option<&'a str>,
+}
+
+/// Downloads the tarball to a file and verifies it against its checksum,
+/// reusing the cached copy without contacting the server if it was downloaded
+/// before.
+fn fetch(
     client: &Client,
-    name: &str,
-    url: &str,
-    dest: &Path,
-) -> Result<(), DownloadError> {
-    let response = XzDecoder::new(download_progress(client, name, url)?);
-    unarchive(response, dest).map_err(DownloadError::Archive)
+    tarball: &Tarball<'_>,
+    dl_params: &DownloadParams,
+    mut bar: Option<&mut Bar>,
+) -> Result<File, DownloadError> {
+    if let Some(file) = cached(tarball.url, tarball.sha256, dl_params) {
+        match bar {
+            Some(bar) => bar.finish("using cached download"),
+            None => debug!("{}: using cached download", tarball.name),
+        }
+        return Ok(file);
+    }
+    let mut attempt = 1;
+    loop {
+        let (mut file, length) = save(client, tarball, dl_params, bar.as_deref_mut())?;
+        if let Some(expected) = tarball.sha256 {
+            let actual = sha256(&mut file).map_err(DownloadError::Io)?;
+            if actual != *expected {
+                let mismatch = DownloadError::ChecksumMismatch {
+                    url: tarball.url.to_string(),
+                    expected: expected.to_string(),
+                    actual,
+                };
+                if attempt >= dl_params.attempts {
+                    return Err(mismatch);
+                }
+                status!(dl_params.reporter, "{mismatch}, downloading it again");
+                attempt += 1;
+                continue;
+            }
+        }
+        // Only complete and verified tarballs are cached. Without a length,
+        // the download cannot be known to be complete.
+        return match (&dl_params.cache, length, local_path(tarball.url)) {
+            (Some(cache), Some(length), None) => cache
+                .insert(tarball.url, length, file, &dl_params.reporter)
+                .map_err(DownloadError::Io),
+            _ => Ok(file),
+        };
+    }
+}
+
+/// Opens the cached copy of the tarball at `url`, or of any tarball with the
+/// checksum `sha256`, if there is one. Local files are not cached.
+fn cached(url: &str, sha256: Option<&str>, dl_params: &DownloadParams) -> Option<File> {
+    match (&dl_params.cache, local_path(url)) {
+        (Some(cache), None) => cache.get(url, sha256),
+        _ => None,
+    }
 }
 
-fn download_tar_gz(
+/// Downloads the tarball to a file, and returns it along with its length if
+/// known.
+///
+/// If the connection breaks, the download is resumed where it left off.
+fn save(
+    client: &Client,
+    tarball: &Tarball<'_>,
+    dl_params: &DownloadParams,
+    mut bar: Option<&mut Bar>,
+) -> Result<(File, Option<u64>), DownloadError> {
+    let url = tarball.url;
+    if let Some(path) = local_path(url) {
+        let file = open_local(path, url)?;
+        let length = file.metadata().map_err(DownloadError::Io)?.len();
+        if let Some(bar) = bar {
+            bar.finish("using local file");
+        }
+        return Ok((file, Some(length)));
+    }
+    let mut retry = Retry::new(dl_params.attempts, &dl_params.reporter);
+    let mut response = get_from(client, url, 0, &mut retry)?;
+    let length = content_length(&response);
+    if let Some(bar) = bar.as_deref_mut() {
+        bar.set_total(length.unwrap_or(0));
+    }
+
+    let mut file = tempfile::tempfile().map_err(DownloadError::Io)?;
+    let mut written = 0;
+    loop {
+        let interrupted =
+            match copy_body(&mut response, &mut file, &mut written, bar.as_deref_mut()) {
+                Ok(Ok(())) => match length {
+                    Some(length) if written < length => io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("connection closed after {written} of {length} bytes"),
+                    ),
+                    _ => break,
+                },
+                Ok(Err(e)) => e,
+                Err(e) => return Err(DownloadError::Io(e)),
+            };
+        if !retry.wait(&format_args!("failed to download {url}: {interrupted}")) {
+            return Err(DownloadError::Io(interrupted));
+        }
+        response = get_from(client, url, written, &mut retry)?;
+        match range_start(&response) {
+            Some(start) if start == written => {}
+            // The server sent the whole tarball again.
+            None => {
+                debug!("<{}> does not support resuming downloads", url);
+                file.set_len(0)
+                    .and_then(|()| file.rewind())
+                    .map_err(DownloadError::Io)?;
+                written = 0;
+                if let Some(bar) = bar.as_deref_mut() {
+                    bar.set(0);
+                }
+            }
+            Some(start) => {
+                return Err(DownloadError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("asked {url} for bytes from {written} on, got them from {start} on"),
+                )))
+            }
+        }
+    }
+    if let Some(bar) = bar {
+        bar.finish("done");
+    }
+
+    file.rewind().map_err(DownloadError::Io)?;
+    Ok((file, length))
+}
+
+/// Appends the body of `response` to `file`, counting the bytes written.
+///
+/// Failing to read the response is returned as the inner error, since the
+/// download can be resumed, unlike after failing to write the file.
+fn copy_body(
+    response: &mut Response,
+    file: &mut File,
+    written: &mut u64,
+    mut bar: Option<&mut Bar>,
+) -> io::Result<io::Result<()>> {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = match response.read(&mut buf) {
+            Ok(0) => return Ok(Ok(())),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Ok(Err(e)),
+        };
+        file.write_all(&buf[..n])?;
+        *written += n as u64;
+        if let Some(bar) = bar.as_deref_mut() {
+            bar.add(n as u64);
+        }
+    }
+}
+
+/// Fetches the checksum published next to the tarball at `url`, if any.
+/// Tarballs on the dist server have one, but CI artifacts do not. Servers
+/// which refuse to serve it, e.g. with a 403 from a mirror, are taken not to
+/// publish one.
+fn published_sha256(
     client: &Client,
-    name: &str,
     url: &str,
-    dest: &Path,
+    dl_params: &DownloadParams,
+) -> Result<Option<String>, DownloadError> {
+    let url = format!("{url}.sha256");
+    match get_text(client, &url, dl_params.attempts, &dl_params.reporter) {
+        Ok(text) => {
+            let checksum = parse_sha256(&text);
+            if checksum.is_none() {
+                warning!(dl_params.reporter, "ignoring malformed checksum at {url}");
+            }
+            Ok(checksum)
+        }
+        Err(DownloadError::NotFound(_)) => {
+            debug!("no checksum published at <{}>", url);
+            Ok(None)
+        }
+        Err(DownloadError::Reqwest(e)) if e.status().is_some() => {
+            debug!("no checksum published at <{}>: {}", url, e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Parses the output of `sha256sum`, which is the hex digest followed by the
+/// file name.
+fn parse_sha256(s: &str) -> Option<String> {
+    let hash = s.split_whitespace().next()?;
+    if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(hash.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+fn sha256(file: &mut File) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    file.rewind()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Downloads the tarball, and either unpacks it or copies it into a mirror.
+fn download_to(
+    client: &Client,
+    tarball: &Tarball<'_>,
+    dest: Destination<'_>,
+    dl_params: &DownloadParams,
+    bar: Option<&mut Bar>,
 ) -> Result<(), DownloadError> {
-    let response = GzDecoder::new(download_progress(client, name, url)?);
-    unarchive(response, dest).map_err(DownloadError::Archive)
+    match dest {
+        Destination::Unpack(dir) => {
+            let file = BufReader::new(fetch(client, tarball, dl_params, bar)?);
+            if tarball.url.ends_with(".tar.xz") {
+                unarchive(XzDecoder::new(file), dir)
+            } else {
+                unarchive(GzDecoder::new(file), dir)
+            }
+            .map_err(DownloadError::Archive)
+        }
+        Destination::Mirror(dir) => {
+            let path = dl_params
+                .servers
+                .iter()
+                .find_map(|server| tarball.url.strip_prefix(server.as_str()))
+                .ok_or_else(|| {
+                    DownloadError::Io(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} is not on the server being mirrored", tarball.url),
+                    ))
+                })?;
+            let path = dir.join(path.trim_start_matches('/'));
+            if path.is_file() {
+                match bar {
+                    Some(bar) => bar.finish("already downloaded"),
+                    None => debug!("{}: already downloaded", tarball.name),
+                }
+                return Ok(());
+            }
+            let file = fetch(client, tarball, dl_params, bar)?;
+            write_to_mirror(file, &path).map_err(DownloadError::Io)
+        }
+    }
+}
+
+/// Writes a file of a mirror through a temporary file, so that an interrupted
+/// download is not mistaken for a complete one.
+fn write_to_mirror<R: Read>(mut contents: R, path: &Path) -> io::Result<()> {
+    let dir = path.parent().expect("files of a mirror are in a directory");
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    io::copy(&mut contents, &mut file)?;
+    file.persist(path)?;
+    Ok(())
 }
 
 fn unarchive<R: Read>(r: R, dest: &Path) -> Result<(), ArchiveError> {
@@ -520,16 +1616,219 @@ fn unarchive<R: Read>(r: R, dest: &Path) -> Result<(), ArchiveError> {
     Ok(())
 }
 
+/// Downloads the tarball at `url` in either format, verifying it against its
+/// published checksum if it is `checksummed`.
 fn download_tarball(
     client: &Client,
     name: &str,
     url: &str,
-    dest: &Path,
+    checksummed: bool,
+    dest: Destination<'_>,
+    dl_params: &DownloadParams,
+    mut bar: Option<&mut Bar>,
 ) -> Result<(), DownloadError> {
-    match download_tar_xz(client, name, &format!("{url}.xz"), dest) {
+    let xz_url = format!("{url}.xz");
+    // A cached tarball was verified when it was stored, and is checked
+    // against the checksum it was stored under, so the published checksum is
+    // not needed again.
+    let sha256 = |url: &str| match cached(url, None, dl_params) {
+        None if checksummed => published_sha256(client, url, dl_params),
+        _ => Ok(None),
+    };
+    let xz_sha256 = sha256(&xz_url)?;
+    let xz = Tarball {
+        name,
+        url: &xz_url,
+        sha256: xz_sha256.as_deref(),
+    };
+    match download_to(client, &xz, dest, dl_params, bar.as_deref_mut()) {
         Err(DownloadError::NotFound { .. }) => {
-            download_tar_gz(client, name, &format!("{url}.gz"), dest)
+            let gz_url = format!("{url}.gz");
+            let gz_sha256 = sha256(&gz_url)?;
+            let gz = Tarball {
+                name,
+                url: &gz_url,
+                sha256: gz_sha256.as_deref(),
+            };
+            download_to(client, &gz, dest, dl_params, bar)
         }
         res => res,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_published_checksum() {
+        let hash = "9f4ba4ec5f3ab1e5e8e4ff0e1e5c3ddc8d1f3c0c8b7e3a2f6c1d0e9b8a7f6e5d";
+        assert_eq!(
+            parse_sha256(&format!(
+                "{hash}  rustc-nightly-x86_64-unknown-linux-gnu.tar.xz\n"
+            )),
+            Some(hash.to_string())
+        );
+        assert_eq!(parse_sha256(&hash.to_uppercase()), Some(hash.to_string()));
+        assert_eq!(parse_sha256("<html>not found</html>"), None);
+        assert_eq!(parse_sha256(""), None);
+    }
+
+    /// Serves each of `responses` to a new connection on localhost, and
+    /// returns the URL to send requests to along with the requests received.
+    fn serve(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rustc.tar.xz", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut request = Vec::new();
+                    let mut byte = [0];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        stream.read_exact(&mut byte).unwrap();
+                        request.push(byte[0]);
+                    }
+                    stream.write_all(response.as_bytes()).unwrap();
+                    String::from_utf8(request).unwrap().to_ascii_lowercase()
+                })
+                .collect()
+        });
+        (url, server)
+    }
+
+    fn dl_params(attempts: u32, cache: Option<Cache>) -> DownloadParams {
+        DownloadParams {
+            servers: Vec::new(),
+            tmp_dir: PathBuf::new(),
+            install_dir: PathBuf::new(),
+            components: Vec::new(),
+            force_install: false,
+            cache,
+            progress: false,
+            attempts,
+            reporter: Reporter::new(|_| {}),
+        }
+    }
+
+    fn download(url: &str, attempts: u32) -> Result<String, DownloadError> {
+        let dl_params = dl_params(attempts, None);
+        let tarball = Tarball {
+            name: "rustc",
+            url,
+            sha256: None,
+        };
+        let (mut file, _) = save(&Client::new(), &tarball, &dl_params, None)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        Ok(contents)
+    }
+
+    #[test]
+    fn resumes_interrupted_download() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123",
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\n\
+             Content-Range: bytes 4-9/10\r\nConnection: close\r\n\r\n456789",
+        ]);
+        assert_eq!(download(&url, 2).unwrap(), "0123456789");
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=4-\r\n"));
+    }
+
+    #[test]
+    fn retries_transient_errors_only() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]);
+        assert_eq!(download(&url, 2).unwrap(), "ok");
+        server.join().unwrap();
+
+        let (url, server) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        assert!(matches!(download(&url, 3), Err(DownloadError::NotFound(_))));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reads_local_mirror() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rustc.tar.xz");
+        fs::write(&path, "local").unwrap();
+        let url = format!("file://{}", path.display());
+        assert_eq!(download(&url, 1).unwrap(), "local");
+        assert_eq!(
+            get_text(&Client::new(), &url, 1, &Reporter::new(|_| {})).unwrap(),
+            "local"
+        );
+
+        let missing = format!("file://{}", dir.path().join("rustc.tar.gz").display());
+        assert!(matches!(
+            download(&missing, 1),
+            Err(DownloadError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn uses_cache_without_contacting_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf(), 1024).unwrap();
+        let dl_params = dl_params(1, Some(cache));
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]);
+        let sha256 = format!("{:x}", Sha256::digest(b"ok"));
+        let tarball = Tarball {
+            name: "rustc",
+            url: &url,
+            sha256: Some(&sha256),
+        };
+        let read = |file: Result<File, DownloadError>| {
+            let mut contents = String::new();
+            file.unwrap().read_to_string(&mut contents).unwrap();
+            contents
+        };
+        assert_eq!(
+            read(fetch(&Client::new(), &tarball, &dl_params, None)),
+            "ok"
+        );
+        // The server is gone, which only matters once the cached copy is
+        // found to be corrupt.
+        server.join().unwrap();
+        assert_eq!(
+            read(fetch(&Client::new(), &tarball, &dl_params, None)),
+            "ok"
+        );
+
+        // A mirror with the same tarball.
+        let mirror = Tarball {
+            url: "http://127.0.0.1:1/rustc.tar.xz",
+            ..tarball
+        };
+        assert_eq!(read(fetch(&Client::new(), &mirror, &dl_params, None)), "ok");
+
+        fs::write(dir.path().join(&sha256), "ko").unwrap();
+        assert!(fetch(&Client::new(), &tarball, &dl_params, None).is_err());
+        assert!(cached(&url, None, &dl_params).is_none());
+    }
+
+    #[test]
+    fn refused_checksum_is_not_published() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let checksum = published_sha256(&Client::new(), &url, &dl_params(1, None));
+        assert!(matches!(checksum, Ok(None)));
+        assert!(server.join().unwrap()[0].contains(".sha256"));
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let delays: Vec<u64> = (1..=8).map(|attempt| backoff(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 32, 32]);
+    }
+}